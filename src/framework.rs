@@ -1,5 +1,5 @@
 use crate::gui::UiMode;
-use crate::light_garden::LightGarden;
+use crate::light_garden::{LightGarden, RenderMode};
 use crate::{gui::Gui, renderer::Renderer};
 use std::sync::Arc;
 use wgpu::{Adapter, ExperimentalFeatures};
@@ -223,12 +223,16 @@ impl ApplicationHandler<Setup> for AppState {
                     surface_texture.present();
                     #[cfg(not(target_arch = "wasm32"))]
                     if let Some(path) = gui.app.screenshot_path.take() {
-                        pollster::block_on(renderer.make_screenshot(
-                            path,
-                            device,
-                            queue,
-                            gui.app.get_render_to_texture(),
-                        ));
+                        if gui.app.tracer.render_mode == RenderMode::Density {
+                            gui.app.save_density_image(&path);
+                        } else {
+                            pollster::block_on(renderer.make_screenshot(
+                                path,
+                                device,
+                                queue,
+                                gui.app.get_render_to_texture(),
+                            ));
+                        }
                     }
                     setup.window.request_redraw();
                 }
@@ -28,6 +28,7 @@ impl Gui {
                             }
                             UiMode::Settings => self.ui_mode = UiMode::Main,
                             UiMode::Grid => self.ui_mode = UiMode::Main,
+                            UiMode::Statistics => self.ui_mode = UiMode::Main,
                             UiMode::StringMod => {
                                 self.app.mode = Mode::StringMod;
                                 self.ui_mode = UiMode::Main;
@@ -41,6 +42,7 @@ impl Gui {
                         (Key::Character("b"), _ui_mode) => self.app.tracer.debug_key_pressed = true,
                         (Key::Character("a"), UiMode::Main) => self.ui_mode = UiMode::Add,
                         (Key::Character("e"), UiMode::Main) => self.ui_mode = UiMode::Settings,
+                        (Key::Character("i"), UiMode::Main) => self.ui_mode = UiMode::Statistics,
                         (Key::Character("t"), UiMode::Main) => {
                             self.ui_mode = UiMode::TileMap;
                             self.app.mode = Mode::SelectTile;
@@ -57,10 +59,14 @@ impl Gui {
                         (Key::Character("d"), UiMode::Add) => {
                             self.app.mode = Mode::DrawDirectionalLightStart
                         }
+                        (Key::Character("l"), UiMode::Add) => self.app.mode = Mode::DrawLaserStart,
 
                         (Key::Character("r"), UiMode::Add) => self.app.mode = Mode::DrawRectStart,
                         (Key::Character("c"), UiMode::Add) => self.app.mode = Mode::DrawCircleStart,
                         (Key::Character("m"), UiMode::Add) => self.app.mode = Mode::DrawMirrorStart,
+                        (Key::Character("t"), UiMode::Add) => {
+                            self.app.mode = Mode::DrawDetectorStart
+                        }
                         (Key::Character("v"), UiMode::Add) => {
                             self.app.mode = Mode::DrawConvexPolygon { points: Vec::new() }
                         }
@@ -69,7 +75,7 @@ impl Gui {
                         }
 
                         (Key::Character("e"), UiMode::Selected) => self.app.mode = Mode::EditObject,
-                        (Key::Character("r"), UiMode::Selected) => self.app.mode = Mode::Rotate,
+                        (Key::Character("r"), UiMode::Selected) => self.app.rotate(),
                         (Key::Character("a"), UiMode::Selected) => {
                             self.app.mode = Mode::Selecting(Some(LogicOp::And))
                         }
@@ -11,6 +11,7 @@ use rfd::FileDialog;
 mod grid;
 mod input;
 mod settings;
+mod statistics;
 mod string_mod;
 mod tile_map;
 
@@ -89,6 +90,9 @@ impl Gui {
                         UiMode::TileMap => {
                             self.tile_map(ui);
                         }
+                        UiMode::Statistics => {
+                            self.statistics(ui);
+                        }
                         UiMode::StringMod => {
                             self.string_mod_selector(ui);
                             Gui::string_mod(ui, self.get_current_string_mod());
@@ -102,6 +106,9 @@ impl Gui {
                         "Average Trace Time: {:.2}",
                         self.app.tracer.get_trace_time()
                     ));
+                    if self.app.is_tracing() {
+                        ui.label("tracing…");
+                    }
                     self.gui_contains_pointer = ctx.is_pointer_over_area();
                 });
         }
@@ -154,6 +161,9 @@ impl Gui {
             self.ui_mode = UiMode::StringMod;
             self.app.mode = Mode::StringMod;
         }
+        if ui.button("Stat(i)stics").clicked() {
+            self.ui_mode = UiMode::Statistics;
+        }
 
         #[cfg(not(target_arch = "wasm32"))]
         {
@@ -171,8 +181,15 @@ impl Gui {
     }
 
     fn selected(&mut self, ui: &mut Ui) {
+        // only actual edits invalidate the cached geometry of the object
         if let Some(obj) = self.app.get_selected_object() {
-            Gui::edit_object(obj, ui);
+            let mut edited = obj.clone();
+            Gui::edit_object(&mut edited, ui);
+            if edited != *obj
+                && let Some(obj) = self.app.get_selected_object_mut()
+            {
+                *obj = edited;
+            }
         }
 
         if ui.button("(E)dit").clicked() {
@@ -198,8 +215,15 @@ impl Gui {
 
         self.edit(ui);
 
+        // only actual edits count as a change of the scene
         if let Some(light) = self.app.get_selected_light() {
-            Gui::edit_light(light, ui);
+            let mut edited = light.clone();
+            Gui::edit_light(&mut edited, ui);
+            if edited != *light
+                && let Some(light) = self.app.get_selected_light_mut()
+            {
+                *light = edited;
+            }
         } else {
             let ac = self.app.selected_color;
             let mut color =
@@ -215,12 +239,25 @@ impl Gui {
 
         if let Some(Light::SpotLight(_)) = self.app.get_selected_light() {
             if ui.button("(R)otate").clicked() {
-                self.app.mode = Mode::Rotate;
+                self.app.rotate();
             }
         }
         if let Some(Light::DirectionalLight(_)) = self.app.get_selected_light() {
             if ui.button("(R)otate").clicked() {
-                self.app.mode = Mode::Rotate;
+                self.app.rotate();
+            }
+            if self.app.mode == Mode::Rotate {
+                let handle = match self.app.rotate_handle() {
+                    RotateHandle::Rotation => "Rotation",
+                    RotateHandle::EmissionAngle => "Emission Angle",
+                    RotateHandle::Divergence => "Divergence",
+                };
+                ui.label(format!("Handle: {handle}, (R)otate again for the next one"));
+            }
+        }
+        if let Some(Light::Laser(_)) = self.app.get_selected_light() {
+            if ui.button("(R)otate").clicked() {
+                self.app.rotate();
             }
         }
     }
@@ -236,6 +273,9 @@ impl Gui {
             if ui.button("Add (D)irectionalLight").clicked() {
                 self.app.mode = Mode::DrawDirectionalLightStart;
             }
+            if ui.button("Add (L)aser").clicked() {
+                self.app.mode = Mode::DrawLaserStart;
+            }
             if ui.button("Add (R)ect").clicked() {
                 self.app.mode = Mode::DrawRectStart;
             }
@@ -245,6 +285,9 @@ impl Gui {
             if ui.button("Add (M)irror").clicked() {
                 self.app.mode = Mode::DrawMirrorStart;
             }
+            if ui.button("Add De(t)ector").clicked() {
+                self.app.mode = Mode::DrawDetectorStart;
+            }
             if ui.button("Add Con(v)ex Polygon").clicked() {
                 self.app.mode = Mode::DrawConvexPolygon { points: Vec::new() };
             }
@@ -260,7 +303,7 @@ impl Gui {
     fn edit(&mut self, ui: &mut Ui) {
         if self.app.get_selected_object().is_some() {
             if ui.button("(R)otate").clicked() {
-                self.app.mode = Mode::Rotate;
+                self.app.rotate();
             }
             if ui.button("(A)nd").clicked() {
                 self.app.mode = Mode::Selecting(Some(LogicOp::And));
@@ -283,15 +326,272 @@ impl Gui {
     }
 
     fn edit_object(object: &mut Object, ui: &mut Ui) {
+        if let ObjectE::Detector(detector) = &mut object.object_enum {
+            Gui::edit_detector(detector, ui);
+            return;
+        }
+        // plain mirrors are edited as mirror materials so that they can be made rough
+        let plain_mirror = object.material_opt.is_none();
+        if plain_mirror {
+            object.material_opt = Some(Material::mirror());
+        }
+        Gui::edit_material_kind(&mut object.material_opt, ui);
         if let Some(material) = object.material_mut() {
-            let mut whole: i32 = material.refractive_index.floor() as i32;
-            let mut frac: Float = material.refractive_index - whole as Float;
-            ui.add(Slider::new::<i32>(&mut whole, -10..=10).text("Refractive Index whole part"));
-            ui.add(
-                Slider::new::<f64>(&mut frac, -0.0..=0.999)
-                    .text("Refractive Index fractional part"),
-            );
-            material.refractive_index = whole as Float + frac;
+            match &mut material.kind {
+                MaterialKind::Mirror => {}
+                MaterialKind::Dielectric => {
+                    let mut whole: i32 = material.refractive_index.floor() as i32;
+                    let mut frac: Float = material.refractive_index - whole as Float;
+                    ui.add(
+                        Slider::new::<i32>(&mut whole, -10..=10)
+                            .text("Refractive Index whole part"),
+                    );
+                    ui.add(
+                        Slider::new::<f64>(&mut frac, -0.0..=0.999)
+                            .text("Refractive Index fractional part"),
+                    );
+                    material.refractive_index = whole as Float + frac;
+                    Gui::edit_dispersion(&mut material.dispersion, ui);
+                    let [red, green, blue] = &mut material.absorption;
+                    ui.add(Slider::new::<f32>(red, 0.0..=20.0).text("Absorption Red"));
+                    ui.add(Slider::new::<f32>(green, 0.0..=20.0).text("Absorption Green"));
+                    ui.add(Slider::new::<f32>(blue, 0.0..=20.0).text("Absorption Blue"));
+                    ui.add(
+                        Slider::new::<u32>(&mut material.priority, 0..=10).text("Medium Priority"),
+                    );
+                }
+                MaterialKind::Absorber { absorptance } => {
+                    ui.add(Slider::new::<f32>(absorptance, 0.0..=1.0).text("Absorptance"));
+                }
+                MaterialKind::PartialMirror { reflectance } => {
+                    ui.add(Slider::new::<f32>(reflectance, 0.0..=1.0).text("Reflectance"));
+                }
+                MaterialKind::Dichroic { reflect } => {
+                    let [red, green, blue] = reflect;
+                    ui.add(Slider::new::<f32>(red, 0.0..=1.0).text("Reflect Red"));
+                    ui.add(Slider::new::<f32>(green, 0.0..=1.0).text("Reflect Green"));
+                    ui.add(Slider::new::<f32>(blue, 0.0..=1.0).text("Reflect Blue"));
+                }
+                MaterialKind::Filter { transmit } => {
+                    let [red, green, blue] = transmit;
+                    ui.add(Slider::new::<f32>(red, 0.0..=1.0).text("Pass Red"));
+                    ui.add(Slider::new::<f32>(green, 0.0..=1.0).text("Pass Green"));
+                    ui.add(Slider::new::<f32>(blue, 0.0..=1.0).text("Pass Blue"));
+                }
+            }
+            // absorbers do not scatter
+            if !matches!(material.kind, MaterialKind::Absorber { .. }) {
+                ui.add(Slider::new::<f64>(&mut material.roughness, 0.0..=1.0).text("Roughness"));
+                ui.add(Slider::new::<f64>(&mut material.diffuse, 0.0..=1.0).text("Diffuse"));
+                ui.add(
+                    Slider::new::<usize>(&mut material.scatter_rays, 1..=32).text("Scatter Rays"),
+                );
+            }
+        }
+        // untouched plain mirrors stay without material
+        if plain_mirror && object.material_opt == Some(Material::mirror()) {
+            object.material_opt = None;
+        }
+        Gui::edit_emission(&mut object.emission, ui);
+    }
+
+    fn edit_emission(emission_opt: &mut Option<Emission>, ui: &mut Ui) {
+        let mut emissive = emission_opt.is_some();
+        if ui.checkbox(&mut emissive, "Emissive").changed() {
+            *emission_opt = emissive.then(Emission::default);
+        }
+        let Some(emission) = emission_opt else {
+            return;
+        };
+        let ec = emission.color;
+        let mut color = Color32::from(Rgba::from_rgba_premultiplied(ec[0], ec[1], ec[2], ec[3]));
+        egui::widgets::color_picker::color_edit_button_srgba(
+            ui,
+            &mut color,
+            color_picker::Alpha::OnlyBlend,
+        );
+        let rgba = Rgba::from(color);
+        emission.color = [rgba[0], rgba[1], rgba[2], rgba[3]];
+        ui.add(
+            Slider::new::<f64>(&mut emission.power, 1.0..=1_000_000.0)
+                .logarithmic(true)
+                .text("Power"),
+        );
+        ui.add(Slider::new::<usize>(&mut emission.num_points, 1..=5000).text("Emission Points"));
+        ui.add(Slider::new::<usize>(&mut emission.rays_per_point, 1..=64).text("Rays per Point"));
+
+        let name = |d: &AngularDistribution| match d {
+            AngularDistribution::Lambertian => "Lambertian",
+            AngularDistribution::Uniform => "Uniform",
+            AngularDistribution::Custom { .. } => "Custom",
+        };
+        ComboBox::from_label("Angular Distribution")
+            .selected_text(name(&emission.distribution))
+            .show_ui(ui, |ui| {
+                for d in [
+                    AngularDistribution::Lambertian,
+                    AngularDistribution::Uniform,
+                    AngularDistribution::Custom {
+                        weights: vec![1., 1., 0.5, 0.],
+                    },
+                ] {
+                    if ui
+                        .selectable_label(name(&emission.distribution) == name(&d), name(&d))
+                        .clicked()
+                        && name(&emission.distribution) != name(&d)
+                    {
+                        emission.distribution = d;
+                    }
+                }
+            });
+        if let AngularDistribution::Custom { weights } = &mut emission.distribution {
+            // weights from the normal (0°) to the surface (90°)
+            let num_weights = weights.len();
+            for (ix, weight) in weights.iter_mut().enumerate() {
+                let angle = 90. * ix as f64 / (num_weights - 1).max(1) as f64;
+                ui.add(Slider::new::<f64>(weight, 0.0..=1.0).text(format!("{angle:.0}°")));
+            }
+            ui.horizontal(|ui| {
+                if ui.button("+").clicked() {
+                    weights.push(0.);
+                }
+                if ui.button("-").clicked() && weights.len() > 1 {
+                    weights.pop();
+                }
+            });
+        }
+    }
+
+    fn edit_detector(detector: &mut Detector, ui: &mut Ui) {
+        ui.add(Slider::new::<usize>(&mut detector.num_bins, 1..=512).text("Bins"));
+        ui.checkbox(&mut detector.pass_through, "Pass Through");
+
+        let total_hits: usize = detector.bins.iter().map(|bin| bin.hits).sum();
+        let max_intensity = detector
+            .bins
+            .iter()
+            .map(|bin| color_energy(&bin.color))
+            .fold(0., f32::max);
+        ui.label(format!("Hits: {total_hits}"));
+
+        // histogram of the bins, each bar is drawn in the average color of its hits
+        let (response, painter) =
+            ui.allocate_painter(vec2(ui.available_width(), 100.), Sense::hover());
+        let rect = response.rect;
+        painter.rect_filled(rect, 0., Color32::from_gray(20));
+        if max_intensity > 0. {
+            let bar_width = rect.width() / detector.bins.len() as f32;
+            for (ix, bin) in detector.bins.iter().enumerate() {
+                let height = rect.height() * color_energy(&bin.color) / max_intensity;
+                let max_channel = bin.color.iter().cloned().fold(0., f32::max);
+                let [r, g, b] = bin
+                    .color
+                    .map(|ch| (255. * ch / max_channel.max(f32::EPSILON)) as u8);
+                painter.rect_filled(
+                    egui::Rect::from_min_max(
+                        pos2(rect.left() + ix as f32 * bar_width, rect.bottom() - height),
+                        pos2(rect.left() + (ix + 1) as f32 * bar_width, rect.bottom()),
+                    ),
+                    0.,
+                    Color32::from_rgb(r, g, b),
+                );
+            }
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            if ui.button("Export CSV ...").clicked() {
+                let csv = detector.to_csv();
+                std::thread::spawn(move || {
+                    if let Some(path_buf) =
+                        FileDialog::new().set_file_name("detector.csv").save_file()
+                    {
+                        if let Err(err) = std::fs::write(path_buf, csv) {
+                            println!("Could not write detector data: {err}");
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    fn edit_material_kind(material_opt: &mut Option<Material>, ui: &mut Ui) {
+        let name = |kind: &Option<MaterialKind>| match kind {
+            None | Some(MaterialKind::Mirror) => "Mirror",
+            Some(MaterialKind::Dielectric) => "Dielectric",
+            Some(MaterialKind::Absorber { .. }) => "Absorber",
+            Some(MaterialKind::PartialMirror { .. }) => "Partial Mirror",
+            Some(MaterialKind::Dichroic { .. }) => "Dichroic Mirror",
+            Some(MaterialKind::Filter { .. }) => "Color Filter",
+        };
+        let current = material_opt.map(|material| material.kind);
+        ComboBox::from_label("Material")
+            .selected_text(name(&current))
+            .show_ui(ui, |ui| {
+                for kind in [
+                    Some(MaterialKind::Mirror),
+                    Some(MaterialKind::Dielectric),
+                    Some(MaterialKind::Absorber { absorptance: 1.0 }),
+                    Some(MaterialKind::PartialMirror { reflectance: 0.5 }),
+                    Some(MaterialKind::Dichroic {
+                        reflect: [1., 0., 0.],
+                    }),
+                    Some(MaterialKind::Filter {
+                        transmit: [0., 1., 0.],
+                    }),
+                ] {
+                    if ui
+                        .selectable_label(name(&current) == name(&kind), name(&kind))
+                        .clicked()
+                        && name(&current) != name(&kind)
+                    {
+                        // keep the remaining material properties when switching kinds
+                        *material_opt = kind.map(|kind| Material {
+                            kind,
+                            ..material_opt.unwrap_or_default()
+                        });
+                    }
+                }
+            });
+    }
+
+    fn edit_dispersion(dispersion: &mut Dispersion, ui: &mut Ui) {
+        let name = |d: &Dispersion| match d {
+            Dispersion::None => "None",
+            Dispersion::Cauchy { .. } => "Cauchy",
+            Dispersion::Sellmeier { .. } => "Sellmeier",
+        };
+        ComboBox::from_label("Dispersion")
+            .selected_text(name(dispersion))
+            .show_ui(ui, |ui| {
+                for d in [
+                    Dispersion::None,
+                    Dispersion::cauchy_bk7(),
+                    Dispersion::sellmeier_bk7(),
+                ] {
+                    if ui
+                        .selectable_label(name(dispersion) == name(&d), name(&d))
+                        .clicked()
+                    {
+                        *dispersion = d;
+                    }
+                }
+            });
+        match dispersion {
+            Dispersion::None => {}
+            Dispersion::Cauchy { b } => {
+                ui.add(Slider::new::<f64>(b, 0.0..=0.1).text("Cauchy B [µm²]"));
+            }
+            Dispersion::Sellmeier { b, c } => {
+                for (ix, (bi, ci)) in b.iter_mut().zip(c.iter_mut()).enumerate() {
+                    ui.horizontal(|ui| {
+                        ui.label(format!("B{}", ix + 1));
+                        ui.add(DragValue::new(bi).speed(0.001));
+                        ui.label(format!("C{} [µm²]", ix + 1));
+                        ui.add(DragValue::new(ci).speed(0.001));
+                    });
+                }
+            }
         }
     }
 
@@ -333,6 +633,7 @@ pub enum UiMode {
     Grid,
     StringMod,
     TileMap,
+    Statistics,
     Exiting,
 }
 
@@ -6,19 +6,48 @@ impl Gui {
         ui.add(Slider::new::<u32>(&mut chunk_size, 1..=1000).text("Rayon Chunk Size"));
         self.app.tracer.chunk_size = chunk_size as usize;
 
+        let mut dispersion = self.app.tracer.spectral_samples > 0;
+        if ui.checkbox(&mut dispersion, "Dispersion").changed() {
+            self.app.tracer.spectral_samples = if dispersion { 16 } else { 0 };
+        }
+        if dispersion {
+            ui.add(
+                Slider::new::<usize>(&mut self.app.tracer.spectral_samples, 1..=64)
+                    .text("Spectral Samples"),
+            );
+        }
+
         self.edit_blend(ui);
 
         self.edit_cutoff_color(ui);
 
         self.toggle_render_to_texture(ui);
 
+        self.edit_render_mode(ui);
+
+        self.edit_progressive(ui);
+
+        self.edit_subdivision(ui);
+
+        self.toggle_incremental(ui);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.toggle_background_tracing(ui);
+
         self.toggle_tile_map(ui);
+
+        self.toggle_bvh(ui);
     }
 
     pub fn edit_light(light: &mut Light, ui: &mut Ui) {
         let mut update_light = false;
         match light {
-            Light::PointLight(_point) => { /* no user interface elements to add */ }
+            Light::PointLight(point) => {
+                // axis of the angular profile
+                let mut axis = point.axis * 180. / PI;
+                ui.add(Slider::new::<f64>(&mut axis, -180.0..=180.0).text("Profile Axis"));
+                point.axis = axis * PI / 180.;
+            }
             Light::SpotLight(spot) => {
                 // spot angle
                 // conversion radian -> degrees
@@ -31,7 +60,48 @@ impl Gui {
                     update_light = true;
                 }
             }
-            Light::DirectionalLight(_direction) => {}
+            Light::DirectionalLight(directional_light) => {
+                // conversion radian -> degrees
+                let mut emission_angle = directional_light.emission_angle * 180. / PI;
+                let mut divergence = directional_light.divergence * 180. / PI;
+                let old = (emission_angle, divergence);
+                ui.add(
+                    Slider::new::<f64>(&mut emission_angle, -89.0..=89.0).text("Emission Angle"),
+                );
+                ui.add(Slider::new::<f64>(&mut divergence, 0.0..=180.0).text("Divergence"));
+                if (emission_angle, divergence) != old {
+                    // conversion degrees -> radian
+                    directional_light.emission_angle = emission_angle * PI / 180.;
+                    directional_light.divergence = divergence * PI / 180.;
+                    update_light = true;
+                }
+            }
+            Light::Laser(laser) => {
+                let old_width = laser.width;
+                ui.add(
+                    Slider::new::<f64>(&mut laser.width, 0.0..=1.0)
+                        .logarithmic(true)
+                        .text("Width"),
+                );
+                // conversion radian -> degrees
+                let mut divergence = laser.divergence * 180. / PI;
+                let old_divergence = divergence;
+                ui.add(
+                    Slider::new::<f64>(&mut divergence, 0.0..=45.0)
+                        .logarithmic(true)
+                        .text("Divergence"),
+                );
+                if (divergence - old_divergence).abs() > Float::EPSILON {
+                    laser.divergence = divergence * PI / 180.;
+                    update_light = true;
+                }
+                if (laser.width - old_width).abs() > Float::EPSILON {
+                    update_light = true;
+                }
+            }
+        }
+        if let Some(profile) = light.profile_mut() {
+            Gui::edit_profile(profile, ui);
         }
 
         // num rays
@@ -42,6 +112,34 @@ impl Gui {
             light.set_num_rays(Some(num_rays_mut));
         }
 
+        // radiant power, independent of the number of rays
+        ui.add(
+            Slider::new::<f64>(light.power_mut(), 1.0..=1_000_000.0)
+                .logarithmic(true)
+                .text("Power"),
+        );
+
+        // color temperature
+        let mut use_temperature = light.get_temperature().is_some();
+        if ui
+            .checkbox(&mut use_temperature, "Color Temperature")
+            .changed()
+        {
+            *light.temperature_mut() = use_temperature.then(ColorTemperature::default);
+        }
+        if let Some(temperature) = light.temperature_mut() {
+            ui.add(
+                Slider::new::<f64>(&mut temperature.kelvin, 1000.0..=12000.0)
+                    .text("Temperature [K]"),
+            );
+            ui.add(
+                Slider::new::<f32>(&mut temperature.intensity, 0.0001..=1.0)
+                    .logarithmic(true)
+                    .text("Intensity"),
+            );
+            return;
+        }
+
         // light color
         let lc = light.get_color();
         let mut color = Color32::from(Rgba::from_rgba_premultiplied(lc[0], lc[1], lc[2], lc[3]));
@@ -143,6 +241,200 @@ impl Gui {
         self.app.tracer.cutoff_color = color;
     }
 
+    fn edit_profile(profile: &mut AngularProfile, ui: &mut Ui) {
+        let name = |p: &AngularProfile| match p {
+            AngularProfile::Uniform => "Uniform",
+            AngularProfile::Cosine { .. } => "Cosine",
+            AngularProfile::Gaussian { .. } => "Gaussian",
+            AngularProfile::SoftSpot { .. } => "Soft Spot",
+            AngularProfile::Tabulated { .. } => "Tabulated",
+        };
+        ComboBox::from_label("Angular Profile")
+            .selected_text(name(profile))
+            .show_ui(ui, |ui| {
+                for p in [
+                    AngularProfile::Uniform,
+                    AngularProfile::Cosine { exponent: 1. },
+                    AngularProfile::Gaussian { sigma: 0.3 },
+                    AngularProfile::SoftSpot {
+                        inner: 0.2,
+                        outer: 0.4,
+                    },
+                    AngularProfile::Tabulated {
+                        angles: vec![0., 15., 30., 45., 60., 75., 90.],
+                        values: vec![1., 0.9, 0.7, 0.4, 0.2, 0.05, 0.],
+                    },
+                ] {
+                    if ui
+                        .selectable_label(name(profile) == name(&p), name(&p))
+                        .clicked()
+                        && name(profile) != name(&p)
+                    {
+                        *profile = p;
+                    }
+                }
+            });
+        match profile {
+            AngularProfile::Uniform => {}
+            AngularProfile::Cosine { exponent } => {
+                ui.add(
+                    Slider::new::<f64>(exponent, 0.0..=100.0)
+                        .logarithmic(true)
+                        .text("Exponent"),
+                );
+            }
+            AngularProfile::Gaussian { sigma } => {
+                let mut degrees = sigma.to_degrees();
+                ui.add(Slider::new::<f64>(&mut degrees, 0.1..=180.0).text("Sigma"));
+                *sigma = degrees.to_radians();
+            }
+            AngularProfile::SoftSpot { inner, outer } => {
+                let mut inner_degrees = inner.to_degrees();
+                let mut outer_degrees = outer.to_degrees();
+                ui.add(Slider::new::<f64>(&mut inner_degrees, 0.0..=180.0).text("Inner Angle"));
+                ui.add(Slider::new::<f64>(&mut outer_degrees, 0.0..=180.0).text("Outer Angle"));
+                *inner = inner_degrees.to_radians();
+                *outer = outer_degrees.max(inner_degrees).to_radians();
+            }
+            AngularProfile::Tabulated { angles, values } => {
+                // the curve can be drawn by dragging the values
+                ui.horizontal_wrapped(|ui| {
+                    for (angle, value) in angles.iter().zip(values.iter_mut()) {
+                        ui.add(
+                            DragValue::new(value)
+                                .speed(0.01)
+                                .range(0.0..=1.0)
+                                .prefix(format!("{angle:.0}°: ")),
+                        );
+                    }
+                });
+                #[cfg(not(target_arch = "wasm32"))]
+                {
+                    if ui
+                        .button("Load Table ...")
+                        .on_hover_text("angle intensity pairs or an IES file")
+                        .clicked()
+                    {
+                        if let Some(path_buf) = FileDialog::new().pick_file() {
+                            match std::fs::read_to_string(path_buf)
+                                .map_err(|e| e.to_string())
+                                .and_then(|data| AngularProfile::parse(&data))
+                            {
+                                Ok(table) => *profile = table,
+                                Err(e) => println!("Could not load angular profile: {e}"),
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn edit_progressive(&mut self, ui: &mut Ui) {
+        let mut progressive = self.app.tracer.progressive;
+        ui.add(Checkbox::new(&mut progressive, "progressive accumulation"))
+            .on_hover_text("helper lines and selection markers are hidden while accumulating");
+        self.app.set_progressive(progressive);
+        if progressive {
+            ui.horizontal(|ui| {
+                ui.label("Seed");
+                ui.add(DragValue::new(&mut self.app.tracer.progressive_seed));
+            });
+            ui.add(
+                Slider::new::<u32>(&mut self.app.tracer.max_passes, 1..=4096).text("Max Passes"),
+            );
+            ui.label(format!("Passes: {}", self.app.tracer.accumulated_passes()));
+        }
+    }
+
+    pub fn edit_subdivision(&mut self, ui: &mut Ui) {
+        let subdivision = &mut self.app.tracer.subdivision;
+        ui.add(Checkbox::new(
+            &mut subdivision.enabled,
+            "adaptive subdivision",
+        ))
+        .on_hover_text("adds rays between neighbouring rays whose paths split up");
+        if subdivision.enabled {
+            ui.add(
+                Slider::new::<usize>(&mut subdivision.budget, 0..=100_000)
+                    .logarithmic(true)
+                    .text("Extra Rays per Light"),
+            );
+            ui.add(Slider::new::<u32>(&mut subdivision.max_depth, 1..=16).text("Max Depth"));
+            ui.add(
+                Slider::new::<f64>(&mut subdivision.max_distance, 0.001..=1.0)
+                    .logarithmic(true)
+                    .text("Max Distance"),
+            );
+        }
+    }
+
+    pub fn toggle_incremental(&mut self, ui: &mut Ui) {
+        let tracer = &mut self.app.tracer;
+        ui.add(Checkbox::new(
+            &mut tracer.incremental,
+            "incremental re-tracing",
+        ))
+        .on_hover_text(
+            "only traces the rays again whose paths cross a changed object, not used with progressive rendering or subdivision",
+        );
+        if tracer.incremental {
+            ui.label(format!("Traced Rays: {}", tracer.retraced_rays()));
+            ui.add(Checkbox::new(
+                &mut tracer.frame_budget_enabled,
+                "frame budget",
+            ))
+            .on_hover_text(
+                "traces an evenly spread subset of the rays first and fills in the rest over the following frames",
+            );
+            if tracer.frame_budget_enabled {
+                ui.add(
+                    Slider::new::<f64>(&mut tracer.frame_budget, 1.0..=100.0)
+                        .logarithmic(true)
+                        .text("Budget (ms)"),
+                );
+                ui.label(format!("Pending Rays: {}", tracer.pending_rays()));
+            }
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn toggle_background_tracing(&mut self, ui: &mut Ui) {
+        let mut background_tracing = self.app.background_tracing();
+        if ui
+            .add(Checkbox::new(&mut background_tracing, "background tracing"))
+            .on_hover_text(
+                "traces on a worker thread and shows the previous result until the new one is ready, not used with progressive rendering or the density mode",
+            )
+            .changed()
+        {
+            self.app.set_background_tracing(background_tracing);
+        }
+    }
+
+    pub fn edit_render_mode(&mut self, ui: &mut Ui) {
+        let mut render_mode = self.app.tracer.render_mode;
+        let name = |mode: RenderMode| match mode {
+            RenderMode::Lines => "Lines",
+            RenderMode::Density => "Density",
+        };
+        ComboBox::from_label("Render Mode")
+            .selected_text(name(render_mode))
+            .show_ui(ui, |ui| {
+                for mode in [RenderMode::Lines, RenderMode::Density] {
+                    ui.selectable_value(&mut render_mode, mode, name(mode));
+                }
+            });
+        self.app.set_render_mode(render_mode);
+        if render_mode == RenderMode::Density {
+            ui.add(
+                Slider::new::<f32>(&mut self.app.tracer.density.exposure, 0.001..=1000.0)
+                    .logarithmic(true)
+                    .text("Exposure"),
+            );
+        }
+    }
+
     pub fn toggle_render_to_texture(&mut self, ui: &mut Ui) {
         let mut render_to_texture = self.app.get_render_to_texture();
         ui.add(Checkbox::new(&mut render_to_texture, "render to texture"));
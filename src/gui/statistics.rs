@@ -0,0 +1,71 @@
+use super::*;
+
+impl Gui {
+    /// energy balance of the last trace per light and emissive object
+    pub fn statistics(&mut self, ui: &mut Ui) {
+        let report = self.app.tracer.energy_report();
+        let Some(total) = report.total() else {
+            ui.label("Nothing traced yet");
+            return;
+        };
+        ui.label("Energy is the sum of the rgb channels of the rays of the last trace");
+        ui.collapsing("Total", |ui| Gui::source_energy(ui, "total", &total));
+        for source in &report.sources {
+            let name = match source.source {
+                PathSource::Light(ix) => format!("Light {ix}"),
+                PathSource::Object(ix) => format!("Emissive Object {ix}"),
+            };
+            ui.collapsing(&name, |ui| Gui::source_energy(ui, &name, source));
+        }
+    }
+
+    fn source_energy(ui: &mut Ui, id: &str, energy: &SourceEnergy) {
+        let share = |value: f32| {
+            if energy.emitted > 0. {
+                format!("{value:.4} ({:.1}%)", 100. * value / energy.emitted)
+            } else {
+                format!("{value:.4}")
+            }
+        };
+        egui::Grid::new(format!("{id} balance"))
+            .striped(true)
+            .show(ui, |ui| {
+                for (label, value) in [
+                    ("Emitted", energy.emitted),
+                    ("Escaped", energy.escaped),
+                    ("Absorbed", energy.absorbed()),
+                    ("Cutoff", energy.cutoff),
+                    ("Max Bounce", energy.max_bounce),
+                    ("Unaccounted", energy.unaccounted()),
+                ] {
+                    ui.label(label);
+                    ui.label(share(value));
+                    ui.end_row();
+                }
+                if energy.pending_rays > 0 {
+                    ui.label("Pending Rays");
+                    ui.label(format!("{} (not included)", energy.pending_rays));
+                    ui.end_row();
+                }
+            });
+        ui.separator();
+        egui::Grid::new(format!("{id} objects"))
+            .striped(true)
+            .show(ui, |ui| {
+                for label in ["Object", "Incident", "Reflected", "Transmitted", "Absorbed"] {
+                    ui.label(label);
+                }
+                ui.end_row();
+                for (ix, obj) in energy.objects.iter().enumerate() {
+                    if obj.incident == 0. && obj.absorbed == 0. {
+                        continue;
+                    }
+                    ui.label(format!("{ix}"));
+                    for value in [obj.incident, obj.reflected, obj.transmitted, obj.absorbed] {
+                        ui.label(format!("{value:.4}"));
+                    }
+                    ui.end_row();
+                }
+            });
+    }
+}
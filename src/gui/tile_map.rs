@@ -6,12 +6,29 @@ impl Gui {
         let mut tilesx = tm.get_num_tiles_x();
         let mut tilesy = tm.get_num_tiles_y();
         let mut nslabs = tm.get_num_slabs();
+        let mut traversal = tm.traversal;
         if ui.add(Slider::new::<usize>(&mut tilesx, 2..=100)).changed()
             || ui.add(Slider::new::<usize>(&mut tilesy, 2..=100)).changed()
             || ui.add(Slider::new::<usize>(&mut nslabs, 4..=32)).changed()
         {
             self.app.tracer.new_tile_map(tilesx, tilesy, nslabs);
         }
+
+        let name = |traversal: TileTraversal| match traversal {
+            TileTraversal::Slabs => "Slabs",
+            TileTraversal::Grid => "Grid (DDA)",
+        };
+        ComboBox::from_label("Traversal")
+            .selected_text(name(traversal))
+            .show_ui(ui, |ui| {
+                for t in [TileTraversal::Slabs, TileTraversal::Grid] {
+                    ui.selectable_value(&mut traversal, t, name(t));
+                }
+            });
+        self.app.tracer.set_tile_traversal(traversal);
+        if self.app.tracer.bvh_enabled {
+            ui.label("the BVH is enabled, the tile map is not used for tracing");
+        }
     }
 
     pub fn select_tile(&self, ui: &mut Ui) {
@@ -36,4 +53,14 @@ impl Gui {
             self.app.tracer.enable_tile_map(tile_map_enabled);
         }
     }
+
+    pub fn toggle_bvh(&mut self, ui: &mut Ui) {
+        ui.add(Checkbox::new(
+            &mut self.app.tracer.bvh_enabled,
+            "BVH enabled",
+        ))
+        .on_hover_text(
+            "find the nearest object with a bounding volume hierarchy instead of the tile map",
+        );
+    }
 }
@@ -0,0 +1,263 @@
+use crate::light_garden::*;
+
+/// objects per leaf of the `Bvh`
+const MAX_LEAF_SIZE: usize = 2;
+
+/// Axis aligned box given by its corners, cheaper to test against rays than `Aabb`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub min: P2,
+    pub max: P2,
+}
+
+impl Bounds {
+    pub fn empty() -> Self {
+        Bounds {
+            min: P2::new(Float::MAX, Float::MAX),
+            max: P2::new(Float::MIN, Float::MIN),
+        }
+    }
+
+    pub fn from_aabb(aabb: &Aabb) -> Self {
+        let (top, left, bottom, right) = aabb.get_tlbr();
+        Bounds {
+            min: P2::new(left.min(right), bottom.min(top)),
+            max: P2::new(left.max(right), bottom.max(top)),
+        }
+    }
+
+    pub fn union(&self, other: &Bounds) -> Bounds {
+        Bounds {
+            min: P2::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y)),
+            max: P2::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y)),
+        }
+    }
+
+    pub fn center(&self) -> P2 {
+        P2::new(
+            (self.min.x + self.max.x) * 0.5,
+            (self.min.y + self.max.y) * 0.5,
+        )
+    }
+
+    pub fn contains(&self, p: &P2) -> bool {
+        p.x >= self.min.x && p.x <= self.max.x && p.y >= self.min.y && p.y <= self.max.y
+    }
+
+    /// whether the line segment from `a` to `b` touches the box
+    pub fn intersects_segment(&self, a: &P2, b: &P2) -> bool {
+        let ab = b - a;
+        let length = ab.norm();
+        if length < EPSILON {
+            return self.contains(a);
+        }
+        let ray = Ray::from_origin(*a, ab / length);
+        self.entry_distance(&ray)
+            .is_some_and(|entry| entry <= length)
+    }
+
+    /// distance along `ray` at which it enters the box, 0 if it starts inside
+    pub fn entry_distance(&self, ray: &Ray) -> Option<Float> {
+        let origin = ray.get_origin();
+        let direction = ray.get_direction().into_inner();
+        let mut t_min: Float = 0.;
+        let mut t_max = Float::MAX;
+        for (o, d, min, max) in [
+            (origin.x, direction.x, self.min.x, self.max.x),
+            (origin.y, direction.y, self.min.y, self.max.y),
+        ] {
+            if d.abs() < EPSILON {
+                // parallel to the slab
+                if o < min || o > max {
+                    return None;
+                }
+            } else {
+                let inv = 1. / d;
+                let (t0, t1) = ((min - o) * inv, (max - o) * inv);
+                t_min = t_min.max(t0.min(t1));
+                t_max = t_max.min(t0.max(t1));
+                if t_min > t_max {
+                    return None;
+                }
+            }
+        }
+        Some(t_min)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum BvhNode {
+    /// `start..end` into `Bvh::indices`
+    Leaf {
+        bounds: Bounds,
+        start: usize,
+        end: usize,
+    },
+    /// the left child directly follows its parent
+    Inner { bounds: Bounds, right: usize },
+}
+
+impl BvhNode {
+    fn bounds(&self) -> &Bounds {
+        match self {
+            BvhNode::Leaf { bounds, .. } | BvhNode::Inner { bounds, .. } => bounds,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over the AABBs of the objects.
+/// Built by splitting the objects at the median of their centers along the longer axis
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    /// object indices, every leaf covers a range of them
+    indices: Vec<usize>,
+    /// bounds per object index
+    object_bounds: Vec<Bounds>,
+}
+
+impl Bvh {
+    pub fn new(objects: &[Object]) -> Self {
+        let mut bvh = Bvh::default();
+        bvh.rebuild(objects);
+        bvh
+    }
+
+    /// number of objects the hierarchy was built for
+    pub fn num_objects(&self) -> usize {
+        self.object_bounds.len()
+    }
+
+    pub fn rebuild(&mut self, objects: &[Object]) {
+        self.object_bounds = objects
+            .iter()
+            .map(|obj| Bounds::from_aabb(obj.cached_aabb()))
+            .collect();
+        self.indices = (0..objects.len()).collect();
+        self.nodes.clear();
+        if !objects.is_empty() {
+            self.build(0, objects.len());
+        }
+    }
+
+    /// Updates the bounds of the moved objects and their ancestors, the tree is kept.
+    /// Cheaper than a rebuild but the tree degrades if objects move far
+    pub fn refit(&mut self, objects: &[Object]) {
+        if objects.len() != self.object_bounds.len() {
+            self.rebuild(objects);
+            return;
+        }
+        let mut changed = false;
+        for (bounds, obj) in self.object_bounds.iter_mut().zip(objects) {
+            if obj.moved {
+                *bounds = Bounds::from_aabb(obj.cached_aabb());
+                changed = true;
+            }
+        }
+        if changed && !self.nodes.is_empty() {
+            self.refit_node(0);
+        }
+    }
+
+    /// Walks the nodes the ray passes through front to back.
+    /// `hit` tests the object with the given index and returns the distance to its nearest
+    /// intersection, nodes farther away than the nearest hit so far are skipped
+    pub fn traverse(&self, ray: &Ray, mut hit: impl FnMut(usize) -> Option<Float>) {
+        let Some(root) = self.nodes.first() else {
+            return;
+        };
+        let mut nearest = Float::MAX;
+        let mut stack: Vec<(usize, Float)> = Vec::new();
+        if let Some(t) = root.bounds().entry_distance(ray) {
+            stack.push((0, t));
+        }
+        while let Some((node_ix, entry)) = stack.pop() {
+            if entry > nearest {
+                continue;
+            }
+            match &self.nodes[node_ix] {
+                BvhNode::Leaf { start, end, .. } => {
+                    for &obj_ix in &self.indices[*start..*end] {
+                        if let Some(dist) = hit(obj_ix) {
+                            nearest = nearest.min(dist);
+                        }
+                    }
+                }
+                BvhNode::Inner { right, .. } => {
+                    let left = node_ix + 1;
+                    let entry_left = self.nodes[left].bounds().entry_distance(ray);
+                    let entry_right = self.nodes[*right].bounds().entry_distance(ray);
+                    // push the farther child first so the nearer one is visited first
+                    let mut children = [(left, entry_left), (*right, entry_right)];
+                    if entry_left < entry_right {
+                        children.swap(0, 1);
+                    }
+                    for (child, entry) in children {
+                        if let Some(entry) = entry {
+                            stack.push((child, entry));
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// builds the subtree for `indices[start..end]` and returns its node index
+    fn build(&mut self, start: usize, end: usize) -> usize {
+        let bounds = self.indices[start..end]
+            .iter()
+            .fold(Bounds::empty(), |acc, ix| {
+                acc.union(&self.object_bounds[*ix])
+            });
+        let node_ix = self.nodes.len();
+        if end - start <= MAX_LEAF_SIZE {
+            self.nodes.push(BvhNode::Leaf { bounds, start, end });
+            return node_ix;
+        }
+        let centers = self.indices[start..end]
+            .iter()
+            .fold(Bounds::empty(), |acc, ix| {
+                let center = self.object_bounds[*ix].center();
+                acc.union(&Bounds {
+                    min: center,
+                    max: center,
+                })
+            });
+        let split_x = centers.max.x - centers.min.x >= centers.max.y - centers.min.y;
+        let object_bounds = &self.object_bounds;
+        let key = |ix: &usize| {
+            let center = object_bounds[*ix].center();
+            if split_x { center.x } else { center.y }
+        };
+        let mid = (start + end) / 2;
+        self.indices[start..end]
+            .select_nth_unstable_by(mid - start, |a, b| key(a).total_cmp(&key(b)));
+        // the right child index is filled in after the left subtree is built
+        self.nodes.push(BvhNode::Inner { bounds, right: 0 });
+        self.build(start, mid);
+        let right_ix = self.build(mid, end);
+        self.nodes[node_ix] = BvhNode::Inner {
+            bounds,
+            right: right_ix,
+        };
+        node_ix
+    }
+
+    fn refit_node(&mut self, node_ix: usize) -> Bounds {
+        let bounds = match self.nodes[node_ix] {
+            BvhNode::Leaf { start, end, .. } => self.indices[start..end]
+                .iter()
+                .fold(Bounds::empty(), |acc, ix| {
+                    acc.union(&self.object_bounds[*ix])
+                }),
+            BvhNode::Inner { right, .. } => {
+                let left = self.refit_node(node_ix + 1);
+                left.union(&self.refit_node(right))
+            }
+        };
+        match &mut self.nodes[node_ix] {
+            BvhNode::Leaf { bounds: b, .. } | BvhNode::Inner { bounds: b, .. } => *b = bounds,
+        }
+        bounds
+    }
+}
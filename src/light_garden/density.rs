@@ -0,0 +1,138 @@
+use crate::light_garden::*;
+
+/// How the traced rays end up on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RenderMode {
+    /// every ray segment is drawn as a line
+    #[default]
+    Lines,
+    /// the energy of the ray segments is splatted into a `DensityBuffer` on the cpu
+    Density,
+}
+
+/// Accumulates the energy of ray segments per pixel, weighted by the length of the segments
+/// inside of the pixels. Covers the same area as the projection of the renderer:
+/// x in [-aspect, aspect] and y in [-1, 1] with the first row at the top
+#[derive(Debug, Clone, PartialEq)]
+pub struct DensityBuffer {
+    width: usize,
+    height: usize,
+    data: Vec<[f32; 3]>,
+    /// multiplies the density before tone mapping
+    pub exposure: f32,
+}
+
+impl DensityBuffer {
+    pub fn new(width: usize, height: usize) -> Self {
+        DensityBuffer {
+            width,
+            height,
+            data: vec![[0.; 3]; width * height],
+            exposure: 1.,
+        }
+    }
+
+    pub fn get_width(&self) -> usize {
+        self.width
+    }
+
+    pub fn get_height(&self) -> usize {
+        self.height
+    }
+
+    /// resizing clears the buffer
+    pub fn resize(&mut self, width: usize, height: usize) {
+        self.width = width;
+        self.height = height;
+        self.data = vec![[0.; 3]; width * height];
+    }
+
+    pub fn clear(&mut self) {
+        self.data.fill([0.; 3]);
+    }
+
+    /// splats line segments given as pairs of points like the output of `Tracer::trace_all`
+    pub fn splat_lines(&mut self, lines: &[(P2, Color)]) {
+        for segment in lines.chunks_exact(2) {
+            self.splat_segment(&segment[0], &segment[1]);
+        }
+    }
+
+    /// Deposits the rgb energy of a segment per pixel length, the color is interpolated
+    /// between the ends. Samples are distributed bilinearly for a smooth result
+    pub fn splat_segment(&mut self, (a, color_a): &(P2, Color), (b, color_b): &(P2, Color)) {
+        if self.width == 0 || self.height == 0 {
+            return;
+        }
+        let pa = self.to_pixel(a);
+        let pb = self.to_pixel(b);
+        let length = (pb - pa).norm();
+        // two samples per pixel the segment crosses
+        let num_samples = (length * 2.).ceil().max(1.) as usize;
+        let step_length = (length / num_samples as Float) as f32;
+        for i in 0..num_samples {
+            let t = (i as Float + 0.5) / num_samples as Float;
+            let p = pa + (pb - pa) * t;
+            let t = t as f32;
+            let energy = [0, 1, 2].map(|c| (color_a[c] * (1. - t) + color_b[c] * t) * step_length);
+            self.deposit(p.x - 0.5, p.y - 0.5, energy);
+        }
+    }
+
+    /// The tone mapped image as linear rgba, row by row from the top.
+    /// `scale` is applied before the exposure, e.g. 1 / number of accumulated passes
+    pub fn tone_mapped(&self, scale: f32) -> Vec<[f32; 4]> {
+        let factor = scale * self.exposure;
+        self.data
+            .iter()
+            .map(|texel| {
+                let [red, green, blue] = texel.map(|channel| {
+                    // reinhard
+                    let v = channel * factor;
+                    v / (1. + v)
+                });
+                [red, green, blue, 1.]
+            })
+            .collect()
+    }
+
+    /// the tone mapped image as srgb encoded rgb bytes, e.g. for `image::save_buffer`
+    pub fn to_rgb8(&self, scale: f32) -> Vec<u8> {
+        self.tone_mapped(scale)
+            .iter()
+            .flat_map(|[red, green, blue, _]| {
+                let encode = |channel: f32| (channel.powf(1. / 2.2) * 255.).round() as u8;
+                [encode(*red), encode(*green), encode(*blue)]
+            })
+            .collect()
+    }
+
+    /// continuous pixel coordinates, the center of the top left pixel is at (0.5, 0.5)
+    fn to_pixel(&self, p: &P2) -> P2 {
+        let aspect = self.width as Float / self.height as Float;
+        P2::new(
+            (p.x + aspect) / (2. * aspect) * self.width as Float,
+            (1. - p.y) * 0.5 * self.height as Float,
+        )
+    }
+
+    fn deposit(&mut self, x: Float, y: Float, energy: [f32; 3]) {
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = ((x - x0) as f32, (y - y0) as f32);
+        for (dx, dy, weight) in [
+            (0, 0, (1. - fx) * (1. - fy)),
+            (1, 0, fx * (1. - fy)),
+            (0, 1, (1. - fx) * fy),
+            (1, 1, fx * fy),
+        ] {
+            let (px, py) = (x0 as i64 + dx, y0 as i64 + dy);
+            if px < 0 || py < 0 || px >= self.width as i64 || py >= self.height as i64 {
+                continue;
+            }
+            let texel = &mut self.data[py as usize * self.width + px as usize];
+            for (channel, energy) in texel.iter_mut().zip(energy) {
+                *channel += energy * weight;
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+use crate::light_garden::*;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::{FRAC_PI_2, TAU};
+
+/// Turns an object into an area light. Rays start on the objects boundary
+/// and leave it into the half plane the boundary normal points to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Emission {
+    pub color: Color,
+    /// radiant power shared by all emitted rays
+    #[serde(default = "Emission::default_power")]
+    pub power: Float,
+    /// number of points on the boundary rays are emitted from
+    pub num_points: usize,
+    /// number of rays per boundary point
+    pub rays_per_point: usize,
+    pub distribution: AngularDistribution,
+}
+
+impl Default for Emission {
+    fn default() -> Self {
+        Emission {
+            color: [0.01; 4],
+            power: Emission::default_power(),
+            num_points: 200,
+            rays_per_point: 16,
+            distribution: AngularDistribution::Lambertian,
+        }
+    }
+}
+
+/// intensity over the angle between the emitted ray and the boundary normal
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum AngularDistribution {
+    /// intensity falls off with the cosine of the angle, the surface looks equally bright from everywhere
+    Lambertian,
+    /// same intensity in every direction
+    Uniform,
+    /// intensities sampled evenly from the normal (0°) to the surface (90°),
+    /// linearly interpolated in between
+    Custom { weights: Vec<Float> },
+}
+
+impl AngularDistribution {
+    /// angle in radians relative to the boundary normal
+    pub fn weight(&self, angle: Float) -> Float {
+        let angle = angle.abs().min(FRAC_PI_2);
+        match self {
+            AngularDistribution::Lambertian => angle.cos(),
+            AngularDistribution::Uniform => 1.,
+            AngularDistribution::Custom { weights } => match weights.len() {
+                0 => 0.,
+                1 => weights[0].max(0.),
+                len => {
+                    let pos = angle / FRAC_PI_2 * (len - 1) as Float;
+                    let ix = (pos.floor() as usize).min(len - 2);
+                    let t = pos - ix as Float;
+                    (weights[ix] * (1. - t) + weights[ix + 1] * t).max(0.)
+                }
+            },
+        }
+    }
+}
+
+impl Emission {
+    /// the power that gives the default number of rays the brightness emissive objects had
+    /// before they had a power
+    fn default_power() -> Float {
+        3200.
+    }
+
+    /// Generates the rays of the emissive `object`.
+    /// The colors are scaled so that all rays together carry `power` times `color`.
+    /// `jitter` in [0, 1) shifts the sample positions and angles inside of their strata, 0.5 centers them
+    pub fn rays(&self, object: &Object, jitter: Float) -> Vec<(Ray, Color)> {
+        let num_rays = self.rays_per_point.max(1);
+        // (direction relative to the normal, weight)
+        let fan: Vec<(Rot2, Float)> = (0..num_rays)
+            .map(|k| {
+                let angle = ((k as Float + jitter) / num_rays as Float * 2. - 1.) * FRAC_PI_2;
+                (Rot2::new(angle), self.distribution.weight(angle))
+            })
+            .collect();
+        let fan_sum: Float = fan.iter().map(|(_, weight)| weight).sum();
+        let samples = boundary_samples(object, self.num_points.max(1), jitter);
+        let length_sum: Float = samples.iter().map(|(_, _, length)| length).sum();
+        if fan_sum <= 0. || length_sum <= 0. {
+            return Vec::new();
+        }
+
+        let mut rays = Vec::with_capacity(samples.len() * fan.len());
+        for (point, normal, length) in samples {
+            let origin = point + normal * SURFACE_OFFSET;
+            for (rotation, weight) in &fan {
+                let share = self.power * length / length_sum * weight / fan_sum;
+                rays.push((
+                    Ray::from_origin(origin, rotation * normal),
+                    [
+                        self.color[0] * share as f32,
+                        self.color[1] * share as f32,
+                        self.color[2] * share as f32,
+                        self.color[3],
+                    ],
+                ));
+            }
+        }
+        rays
+    }
+}
+
+/// Samples about `num_points` points on the boundary of `object`
+/// as (point, outward normal, length of boundary the sample stands for).
+/// Curves and line segments glow on both sides
+fn boundary_samples(object: &Object, num_points: usize, jitter: Float) -> Vec<(P2, V2, Float)> {
+    match &object.object_enum {
+        ObjectE::StraightMirror(StraightMirror { line_segment })
+        | ObjectE::Detector(Detector { line_segment, .. }) => {
+            let a = line_segment.get_a();
+            let b = line_segment.get_b();
+            curve_samples(|t| a + (b - a) * t, num_points, jitter)
+        }
+        ObjectE::CurvedMirror(cm) => {
+            let p = &cm.cubic.points;
+            curve_samples(
+                |t| {
+                    let s = 1. - t;
+                    P2::from(
+                        p[0].coords * (s * s * s)
+                            + p[1].coords * (3. * s * s * t)
+                            + p[2].coords * (3. * s * t * t)
+                            + p[3].coords * (t * t * t),
+                    )
+                },
+                num_points,
+                jitter,
+            )
+        }
+        _ => outline_samples(object, num_points, jitter),
+    }
+}
+
+/// samples an open curve given by `eval` on [0, 1], once for every side
+fn curve_samples(
+    eval: impl Fn(Float) -> P2,
+    num_points: usize,
+    jitter: Float,
+) -> Vec<(P2, V2, Float)> {
+    let per_side = num_points.div_ceil(2).max(1);
+    let mut res = Vec::with_capacity(2 * per_side);
+    for i in 0..per_side {
+        let t0 = i as Float / per_side as Float;
+        let t1 = (i + 1) as Float / per_side as Float;
+        let chord = eval(t1) - eval(t0);
+        let length = chord.norm();
+        if length < EPSILON {
+            continue;
+        }
+        let normal = V2::new(-chord.y, chord.x) / length;
+        let point = eval(t0 + jitter * (t1 - t0));
+        res.push((point, normal, length));
+        res.push((point, -normal, length));
+    }
+    res
+}
+
+/// a piece of the outline of a closed shape, evaluated on [0, 1]
+type OutlinePiece = Box<dyn Fn(Float) -> P2>;
+
+/// steps to measure the length of an outline piece
+const OUTLINE_STEPS: usize = 64;
+
+/// Samples the outlines of the shapes `object` is made of, each with its share of `num_points`,
+/// and keeps the samples on the boundary of the whole object with the normal pointing out of it.
+/// Covers any shape, also the ones that are combined with logic operations
+fn outline_samples(object: &Object, num_points: usize, jitter: Float) -> Vec<(P2, V2, Float)> {
+    let mut pieces: Vec<OutlinePiece> = Vec::new();
+    outline_pieces(object.cached_geometry().clone(), &mut pieces);
+    let lengths: Vec<Float> = pieces
+        .iter()
+        .map(|eval| {
+            (0..OUTLINE_STEPS)
+                .map(|i| {
+                    distance(
+                        &eval(i as Float / OUTLINE_STEPS as Float),
+                        &eval((i + 1) as Float / OUTLINE_STEPS as Float),
+                    )
+                })
+                .sum()
+        })
+        .collect();
+    let length_sum: Float = lengths.iter().sum();
+    if length_sum <= 0. {
+        return Vec::new();
+    }
+    let mut res = Vec::with_capacity(num_points);
+    for (eval, length) in pieces.iter().zip(lengths) {
+        let count = ((num_points as Float * length / length_sum).round() as usize).max(1);
+        // both sides are sampled, only the outside of the object is kept
+        res.extend(curve_samples(eval, 2 * count, jitter).into_iter().filter(
+            |(point, normal, _)| {
+                let offset = normal * SURFACE_OFFSET;
+                !object.contains(&(point + offset)) && object.contains(&(point - offset))
+            },
+        ));
+    }
+    res
+}
+
+/// collects the outlines of the closed shapes `geo` is made of, open shapes have no inside
+fn outline_pieces(geo: Geo, pieces: &mut Vec<OutlinePiece>) {
+    match geo {
+        Geo::GeoCircle(c) => {
+            let origin = c.get_origin();
+            let radius = c.radius;
+            pieces.push(Box::new(move |t| {
+                let (sine, cosine) = (t * TAU).sin_cos();
+                origin + V2::new(cosine, sine) * radius
+            }));
+        }
+        Geo::GeoEllipse(e) => {
+            pieces.push(Box::new(move |t| {
+                e.origin + e.rot * e.eval_at_t(t * TAU).coords
+            }));
+        }
+        Geo::GeoRect(r) => {
+            for ls in r.line_segments() {
+                push_segment(ls.get_a(), ls.get_b(), pieces);
+            }
+        }
+        Geo::GeoConvexPolygon(cp) => {
+            for ls in cp.get_line_segments() {
+                push_segment(ls.get_a(), ls.get_b(), pieces);
+            }
+        }
+        Geo::GeoLogic(l) => {
+            let a = l.get_a();
+            let b = l.get_b();
+            outline_pieces(a.into(), pieces);
+            outline_pieces(b.into(), pieces);
+        }
+        Geo::GeoMCircle(mc) => {
+            outline_pieces(mc.circle_a().into(), pieces);
+            outline_pieces(mc.circle_b().into(), pieces);
+        }
+        _ => {}
+    }
+}
+
+fn push_segment(a: P2, b: P2, pieces: &mut Vec<OutlinePiece>) {
+    pieces.push(Box::new(move |t| a + (b - a) * t));
+}
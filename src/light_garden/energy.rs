@@ -0,0 +1,201 @@
+use crate::light_garden::*;
+
+/// energy of a color as the sum of its rgb channels, alpha only controls the blending
+pub fn color_energy(color: &[f32]) -> f32 {
+    color.iter().take(3).sum()
+}
+
+/// What happened to the energy arriving at one object
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ObjectEnergy {
+    /// arriving at the surface
+    pub incident: f32,
+    /// leaving the surface on the side it arrived from
+    pub reflected: f32,
+    /// leaving the surface on the other side
+    pub transmitted: f32,
+    /// absorbed at the surface and inside of the material
+    pub absorbed: f32,
+}
+
+/// Where the energy of one light or emissive object ended up
+#[derive(Debug, Clone, PartialEq)]
+pub struct SourceEnergy {
+    pub source: PathSource,
+    pub emitted: f32,
+    /// left the canvas
+    pub escaped: f32,
+    /// fell below `Tracer::cutoff_color`
+    pub cutoff: f32,
+    /// still going after `Tracer::max_bounce`
+    pub max_bounce: f32,
+    /// rays left out by the frame budget, their energy is not part of the other totals
+    pub pending_rays: usize,
+    /// indexed like the objects of the tracer
+    pub objects: Vec<ObjectEnergy>,
+}
+
+impl SourceEnergy {
+    pub fn new(source: PathSource, num_objects: usize) -> Self {
+        SourceEnergy {
+            source,
+            emitted: 0.,
+            escaped: 0.,
+            cutoff: 0.,
+            max_bounce: 0.,
+            pending_rays: 0,
+            objects: vec![ObjectEnergy::default(); num_objects],
+        }
+    }
+
+    pub fn absorbed(&self) -> f32 {
+        self.objects.iter().map(|obj| obj.absorbed).sum()
+    }
+
+    /// emitted energy that is not accounted for, 0 up to rounding errors
+    pub fn unaccounted(&self) -> f32 {
+        self.emitted - self.escaped - self.cutoff - self.max_bounce - self.absorbed()
+    }
+
+    fn add_path(&mut self, path: &RayPath) {
+        if path.pending {
+            self.pending_rays += 1;
+            return;
+        }
+        self.emitted += path.emitted;
+        self.escaped += path.escaped;
+        self.cutoff += path.cutoff;
+        self.max_bounce += path.max_bounce;
+        for segment in &path.segments {
+            if let Some(obj) = segment.medium.and_then(|ix| self.objects.get_mut(ix)) {
+                obj.absorbed += segment.medium_absorbed();
+            }
+            if let Some(obj) = segment.hit.and_then(|ix| self.objects.get_mut(ix)) {
+                obj.incident += color_energy(&segment.end_color);
+                obj.reflected += segment.reflected_energy;
+                obj.transmitted += segment.transmitted_energy;
+                obj.absorbed += segment.surface_absorbed();
+            }
+        }
+    }
+}
+
+/// The energy balance of a trace per light and emissive object, see `Tracer::energy_report`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct EnergyReport {
+    /// lights first, then emissive objects, in the order of their indices
+    pub sources: Vec<SourceEnergy>,
+}
+
+impl EnergyReport {
+    pub fn from_paths(paths: &[RayPath], num_objects: usize) -> Self {
+        let mut sources: Vec<SourceEnergy> = Vec::new();
+        for path in paths {
+            // the paths of a source are next to each other
+            let ix = match sources.iter().rposition(|s| s.source == path.source) {
+                Some(ix) => ix,
+                None => {
+                    sources.push(SourceEnergy::new(path.source, num_objects));
+                    sources.len() - 1
+                }
+            };
+            sources[ix].add_path(path);
+        }
+        sources.sort_by_key(|s| match s.source {
+            PathSource::Light(ix) => (0, ix),
+            PathSource::Object(ix) => (1, ix),
+        });
+        EnergyReport { sources }
+    }
+
+    /// the sums over all sources, with the source of the first one
+    pub fn total(&self) -> Option<SourceEnergy> {
+        let mut sources = self.sources.iter();
+        let mut total = sources.next()?.clone();
+        for s in sources {
+            total.emitted += s.emitted;
+            total.escaped += s.escaped;
+            total.cutoff += s.cutoff;
+            total.max_bounce += s.max_bounce;
+            total.pending_rays += s.pending_rays;
+            for (sum, obj) in total.objects.iter_mut().zip(&s.objects) {
+                sum.incident += obj.incident;
+                sum.reflected += obj.reflected;
+                sum.transmitted += obj.transmitted;
+                sum.absorbed += obj.absorbed;
+            }
+        }
+        Some(total)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// one point light in front of a dielectric slab that absorbs some red
+    fn slab_scene() -> Tracer {
+        let canvas_bounds = Rect::from_tlbr(1., -1.5, -1., 1.5);
+        let mut tracer = Tracer::new(&canvas_bounds);
+        tracer.clear();
+        let mut slab = Object::new_rect(P2::new(0.3, 0.), 0.3, 0.8);
+        if let Some(material) = slab.material_mut() {
+            material.refractive_index = 1.5;
+            material.absorption = [2., 0., 0.];
+        }
+        tracer.push_object(slab);
+        tracer.push_light(Light::PointLight(PointLight::new(
+            P2::new(-0.4, 0.05),
+            500,
+            [0.1, 0.1, 0.1, 1.],
+        )));
+        tracer
+    }
+
+    fn assert_close(a: f32, b: f32, scale: f32) {
+        assert!((a - b).abs() <= 1e-4 * scale.max(1.), "{a} != {b}");
+    }
+
+    fn assert_conserved(tracer: &Tracer) {
+        let report = tracer.energy_report();
+        let total = report.total().expect("the light was traced");
+        assert!(total.emitted > 0.);
+        assert!(total.escaped > 0.);
+        assert!(total.absorbed() > 0.);
+        for source in &report.sources {
+            assert_close(source.unaccounted(), 0., source.emitted);
+        }
+        // the slab is a dielectric, its surface splits without absorbing
+        let slab = total.objects[0];
+        assert!(slab.incident > 0.);
+        assert_close(
+            slab.reflected + slab.transmitted,
+            slab.incident,
+            total.emitted,
+        );
+        for segment in tracer.ray_paths().iter().flat_map(|path| &path.segments) {
+            if segment.hit.is_some() {
+                assert_close(
+                    segment.reflected_energy + segment.transmitted_energy,
+                    color_energy(&segment.end_color),
+                    1.,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn energy_is_conserved() {
+        let mut tracer = slab_scene();
+        tracer.trace_all();
+        assert_conserved(&tracer);
+    }
+
+    #[test]
+    fn energy_is_conserved_with_spectral_samples() {
+        let mut tracer = slab_scene();
+        tracer.spectral_samples = 8;
+        tracer.trace_all();
+        assert_conserved(&tracer);
+    }
+}
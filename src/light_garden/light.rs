@@ -4,6 +4,8 @@ use collision2d::geo::*;
 use na::Vector2;
 use serde::{Deserialize, Serialize};
 
+use super::{AngularProfile, Rng, blackbody_to_rgb};
+
 pub type Color = [f32; 4];
 
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
@@ -11,6 +13,7 @@ pub enum Light {
     PointLight(PointLight),
     DirectionalLight(DirectionalLight),
     SpotLight(SpotLight),
+    Laser(Laser),
 }
 
 impl Light {
@@ -19,6 +22,7 @@ impl Light {
             Light::PointLight(l) => &l.rays,
             Light::DirectionalLight(l) => &l.rays,
             Light::SpotLight(l) => &l.rays,
+            Light::Laser(l) => &l.rays,
         }
     }
     pub fn color_mut(&mut self) -> &mut Color {
@@ -26,13 +30,39 @@ impl Light {
             Light::PointLight(l) => &mut l.color,
             Light::DirectionalLight(l) => &mut l.color,
             Light::SpotLight(l) => &mut l.color,
+            Light::Laser(l) => &mut l.color,
         }
     }
+    /// the color picked by the user or the color of the lights temperature
     pub fn get_color(&self) -> Color {
-        match self {
+        let color = match self {
             Light::PointLight(l) => l.color,
             Light::DirectionalLight(l) => l.color,
             Light::SpotLight(l) => l.color,
+            Light::Laser(l) => l.color,
+        };
+        match self.get_temperature() {
+            Some(temperature) => {
+                let [red, green, blue] = temperature.to_rgb();
+                [red, green, blue, color[3]]
+            }
+            None => color,
+        }
+    }
+    pub fn get_temperature(&self) -> Option<ColorTemperature> {
+        match self {
+            Light::PointLight(l) => l.temperature,
+            Light::DirectionalLight(l) => l.temperature,
+            Light::SpotLight(l) => l.temperature,
+            Light::Laser(l) => l.temperature,
+        }
+    }
+    pub fn temperature_mut(&mut self) -> &mut Option<ColorTemperature> {
+        match self {
+            Light::PointLight(l) => &mut l.temperature,
+            Light::DirectionalLight(l) => &mut l.temperature,
+            Light::SpotLight(l) => &mut l.temperature,
+            Light::Laser(l) => &mut l.temperature,
         }
     }
     pub fn set_color(&mut self, red: f32, green: f32, blue: f32, alpha: f32) {
@@ -40,6 +70,41 @@ impl Light {
             Light::PointLight(light) => light.set_color([red, green, blue, alpha]),
             Light::DirectionalLight(light) => light.set_color([red, green, blue, alpha]),
             Light::SpotLight(light) => light.set_color([red, green, blue, alpha]),
+            Light::Laser(light) => light.set_color([red, green, blue, alpha]),
+        }
+    }
+    /// the color of a single ray, the lights power is shared by all of its rays
+    pub fn get_ray_color(&self) -> Color {
+        let color = self.get_color();
+        let share = (self.get_power() / self.get_num_rays().max(1) as Float) as f32;
+        [
+            color[0] * share,
+            color[1] * share,
+            color[2] * share,
+            color[3],
+        ]
+    }
+    pub fn get_power(&self) -> Float {
+        match self {
+            Light::PointLight(l) => l.power,
+            Light::DirectionalLight(l) => l.power,
+            Light::SpotLight(l) => l.power,
+            Light::Laser(l) => l.power,
+        }
+    }
+    pub fn power_mut(&mut self) -> &mut Float {
+        match self {
+            Light::PointLight(l) => &mut l.power,
+            Light::DirectionalLight(l) => &mut l.power,
+            Light::SpotLight(l) => &mut l.power,
+            Light::Laser(l) => &mut l.power,
+        }
+    }
+    /// Lights saved before they had a power gave every ray the full color.
+    /// A power equal to the number of rays keeps their brightness
+    pub fn migrate_power(&mut self) {
+        if self.get_power() <= 0. {
+            *self.power_mut() = self.get_num_rays() as Float;
         }
     }
     pub fn set_num_rays(&mut self, num_rays: Option<usize>) {
@@ -47,13 +112,84 @@ impl Light {
             Light::PointLight(l) => l.set_num_rays(num_rays),
             Light::DirectionalLight(l) => l.set_num_rays(num_rays),
             Light::SpotLight(l) => l.set_num_rays(num_rays),
+            Light::Laser(l) => l.set_num_rays(num_rays),
+        }
+    }
+    /// the rays of the light with every ray moved randomly inside of its stratum
+    pub fn jittered_rays(&self, rng: &mut Rng) -> Vec<Ray> {
+        match self {
+            Light::PointLight(l) => l.generate_rays(|| rng.next_float()),
+            Light::DirectionalLight(l) => l.generate_rays(|| rng.next_float()),
+            Light::SpotLight(l) => l.generate_rays(|| rng.next_float()),
+            Light::Laser(l) => l.generate_rays(|| rng.next_float()),
+        }
+    }
+    /// the angular profile if the light has an axis to measure the angles from
+    pub fn profile_mut(&mut self) -> Option<&mut AngularProfile> {
+        match self {
+            Light::PointLight(l) => Some(&mut l.profile),
+            Light::DirectionalLight(_) => None,
+            Light::SpotLight(l) => Some(&mut l.profile),
+            Light::Laser(_) => None,
+        }
+    }
+    /// Relative intensities of `rays` according to the lights angular profile.
+    /// They average to 1 so the profile does not change the lights power
+    pub fn ray_weights(&self, rays: &[Ray]) -> Vec<f32> {
+        let (profile, axis) = match self {
+            Light::PointLight(l) => (&l.profile, V2::new(l.axis.cos(), l.axis.sin())),
+            Light::SpotLight(l) => (&l.profile, l.spot_direction),
+            Light::Laser(_) => return vec![1.; rays.len()],
+            Light::DirectionalLight(_) => return vec![1.; rays.len()],
+        };
+        if *profile == AngularProfile::Uniform {
+            return vec![1.; rays.len()];
+        }
+        let weights: Vec<Float> = rays
+            .iter()
+            .map(|ray| profile.intensity(ray.get_direction().angle(&axis)))
+            .collect();
+        let mean = weights.iter().sum::<Float>() / weights.len().max(1) as Float;
+        if mean <= 0. {
+            return vec![0.; rays.len()];
         }
+        weights.iter().map(|w| (w / mean) as f32).collect()
+    }
+    /// whether the last ray is a neighbour of the first one
+    pub fn rays_wrap_around(&self) -> bool {
+        matches!(self, Light::PointLight(_))
     }
     pub fn get_num_rays(&self) -> usize {
         match self {
             Light::PointLight(l) => l.num_rays,
             Light::DirectionalLight(l) => l.num_rays,
             Light::SpotLight(l) => l.num_rays,
+            Light::Laser(l) => l.num_rays,
+        }
+    }
+}
+
+/// color of a black body radiator
+#[derive(PartialEq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct ColorTemperature {
+    pub kelvin: Float,
+    /// scales the luminance of the color which is 1 otherwise
+    pub intensity: f32,
+}
+
+impl ColorTemperature {
+    /// linear rgb, ready to be added up by the blend state
+    pub fn to_rgb(&self) -> [f32; 3] {
+        blackbody_to_rgb(self.kelvin).map(|channel| channel * self.intensity)
+    }
+}
+
+impl Default for ColorTemperature {
+    fn default() -> Self {
+        // daylight
+        ColorTemperature {
+            kelvin: 6500.,
+            intensity: 0.03,
         }
     }
 }
@@ -64,6 +200,7 @@ impl HasOrigin for Light {
             Light::PointLight(l) => l.get_origin(),
             Light::DirectionalLight(l) => l.get_origin(),
             Light::SpotLight(l) => l.get_origin(),
+            Light::Laser(l) => l.get_origin(),
         }
     }
     fn set_origin(&mut self, origin: P2) {
@@ -71,6 +208,7 @@ impl HasOrigin for Light {
             Light::PointLight(l) => l.set_origin(origin),
             Light::DirectionalLight(l) => l.set_origin(origin),
             Light::SpotLight(l) => l.set_origin(origin),
+            Light::Laser(l) => l.set_origin(origin),
         }
     }
 }
@@ -78,19 +216,35 @@ impl HasOrigin for Light {
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct DirectionalLight {
     color: Color,
+    /// radiant power shared by all rays, 0 in scenes saved before lights had a power
+    #[serde(default)]
+    power: Float,
+    /// replaces the rgb part of `color` if set
+    #[serde(default)]
+    temperature: Option<ColorTemperature>,
     #[serde(skip)]
     rays: Vec<Ray>,
     num_rays: usize,
     start: LineSegment,
+    /// angle between the rays and the normal of `start` in radians, counterclockwise
+    #[serde(default)]
+    pub emission_angle: Float,
+    /// full angle between the outermost rays in radians, the rays spread out from the center
+    #[serde(default)]
+    pub divergence: Float,
 }
 
 impl DirectionalLight {
     pub fn new(color: Color, num_rays: usize, start: LineSegment) -> Self {
         let mut ret = DirectionalLight {
             color,
+            power: num_rays as Float,
+            temperature: None,
             rays: Vec::new(),
             num_rays,
             start,
+            emission_angle: 0.,
+            divergence: 0.,
         };
         ret.set_num_rays(None);
         ret
@@ -104,15 +258,78 @@ impl DirectionalLight {
         if let Some(n) = num_rays {
             self.num_rays = n;
         }
-        self.rays = Vec::with_capacity(self.num_rays);
-        let n = self.start.get_normal();
-        for i in 0..self.num_rays {
-            self.rays.push(Ray::from_origin(
-                self.start.eval_at_r(-(i as f64) / self.num_rays as f64),
-                n.into_inner(),
-            ));
+        self.rays = self.generate_rays(|| 0.);
+    }
+
+    /// `offset` places each ray inside of its stratum, in [0, 1)
+    fn generate_rays(&self, mut offset: impl FnMut() -> Float) -> Vec<Ray> {
+        let n = self.start.get_normal().into_inner();
+        let a = self.start.get_a();
+        let center = self.center();
+        let length = (self.start.get_b() - a).norm().max(EPSILON);
+        (0..self.num_rays)
+            .map(|i| {
+                let origin = self
+                    .start
+                    .eval_at_r(-(i as f64 + offset()) / self.num_rays as f64);
+                // positive on the counterclockwise side of the normal, in [-0.5, 0.5]
+                let from_center = origin - center;
+                let side = (n.x * from_center.y - n.y * from_center.x) / length;
+                let angle = self.emission_angle + side * self.divergence;
+                Ray::from_origin(origin, rotate_v2(&n, angle))
+            })
+            .collect()
+    }
+
+    /// tilts the emission so the ray from the center of the light points at `target`
+    pub fn emission_look_at(&mut self, target: &P2) {
+        let n = self.start.get_normal().into_inner();
+        let to_target = target - self.center();
+        if to_target.norm() < EPSILON {
+            return;
         }
+        let angle = (n.x * to_target.y - n.y * to_target.x).atan2(n.dot(&to_target));
+        let max_angle = 89_f64.to_radians();
+        self.emission_angle = angle.clamp(-max_angle, max_angle);
+        self.set_num_rays(None);
     }
+
+    /// spreads the emission so the outermost rays on either side point at `target`
+    pub fn divergence_look_at(&mut self, target: &P2) {
+        let (center, [central, _, _]) = self.emission_handles();
+        let to_target = target - center;
+        if to_target.norm() < EPSILON {
+            return;
+        }
+        let angle =
+            (central.x * to_target.y - central.y * to_target.x).atan2(central.dot(&to_target));
+        self.divergence = (2. * angle.abs()).min(std::f64::consts::PI);
+        self.set_num_rays(None);
+    }
+
+    /// center of the light and the directions of its central and its two outermost rays
+    pub fn emission_handles(&self) -> (P2, [V2; 3]) {
+        let n = self.start.get_normal().into_inner();
+        let half_divergence = 0.5 * self.divergence;
+        (
+            self.center(),
+            [
+                rotate_v2(&n, self.emission_angle),
+                rotate_v2(&n, self.emission_angle + half_divergence),
+                rotate_v2(&n, self.emission_angle - half_divergence),
+            ],
+        )
+    }
+
+    fn center(&self) -> P2 {
+        self.start.get_a() + (self.start.get_b() - self.start.get_a()) * 0.5
+    }
+}
+
+/// rotates `v` counterclockwise by `angle` radians
+fn rotate_v2(v: &V2, angle: Float) -> V2 {
+    let (sine, cosine) = angle.sin_cos();
+    V2::new(cosine * v.x - sine * v.y, sine * v.x + cosine * v.y)
 }
 
 impl HasOrigin for DirectionalLight {
@@ -139,6 +356,17 @@ impl Rotate for DirectionalLight {
 pub struct PointLight {
     position: P2,
     pub color: Color,
+    #[serde(default)]
+    pub profile: AngularProfile,
+    /// direction the angular profile is measured from as an angle in radians
+    #[serde(default)]
+    pub axis: Float,
+    /// radiant power shared by all rays, 0 in scenes saved before lights had a power
+    #[serde(default)]
+    power: Float,
+    /// replaces the rgb part of `color` if set
+    #[serde(default)]
+    temperature: Option<ColorTemperature>,
     #[serde(skip)]
     rays: Vec<Ray>,
     num_rays: usize,
@@ -149,6 +377,10 @@ impl PointLight {
         let mut light = PointLight {
             position,
             color,
+            profile: AngularProfile::default(),
+            axis: 0.,
+            power: num_rays as Float,
+            temperature: None,
             rays: Vec::new(),
             num_rays,
         };
@@ -164,13 +396,19 @@ impl PointLight {
         if let Some(n) = num_rays {
             self.num_rays = n;
         }
-        self.rays = Vec::with_capacity(self.num_rays);
-        for i in 0..self.num_rays {
-            let f = i as Float * std::f64::consts::PI * 2. / self.num_rays as Float;
-            let (sine, cosine) = f.sin_cos();
-            self.rays
-                .push(Ray::from_origin(self.position, Vector2::new(cosine, sine)));
-        }
+        self.rays = self.generate_rays(|| 0.);
+    }
+
+    /// `offset` places each ray inside of its stratum, in [0, 1)
+    fn generate_rays(&self, mut offset: impl FnMut() -> Float) -> Vec<Ray> {
+        (0..self.num_rays)
+            .map(|i| {
+                let f =
+                    (i as Float + offset()) * std::f64::consts::PI * 2. / self.num_rays as Float;
+                let (sine, cosine) = f.sin_cos();
+                Ray::from_origin(self.position, Vector2::new(cosine, sine))
+            })
+            .collect()
     }
 }
 
@@ -190,6 +428,14 @@ impl HasOrigin for PointLight {
 pub struct SpotLight {
     position: P2,
     color: Color,
+    #[serde(default)]
+    pub profile: AngularProfile,
+    /// radiant power shared by all rays, 0 in scenes saved before lights had a power
+    #[serde(default)]
+    power: Float,
+    /// replaces the rgb part of `color` if set
+    #[serde(default)]
+    temperature: Option<ColorTemperature>,
     #[serde(skip)]
     rays: Vec<Ray>,
     num_rays: usize,
@@ -208,6 +454,9 @@ impl SpotLight {
         let mut light = SpotLight {
             position,
             color,
+            profile: AngularProfile::default(),
+            power: num_rays as Float,
+            temperature: None,
             rays: Vec::new(),
             num_rays,
             spot_angle,
@@ -226,7 +475,11 @@ impl SpotLight {
         if let Some(n) = num_rays {
             self.num_rays = n;
         }
-        self.rays = Vec::with_capacity(self.num_rays);
+        self.rays = self.generate_rays(|| 1.);
+    }
+
+    /// `offset` places each ray inside of its stratum, in [0, 1]
+    fn generate_rays(&self, mut offset: impl FnMut() -> Float) -> Vec<Ray> {
         let direction_angle = if self.spot_direction.x.abs() < EPSILON {
             if self.spot_direction.y >= 0. {
                 std::f64::consts::PI * 0.5
@@ -237,15 +490,15 @@ impl SpotLight {
             (self.spot_direction.y / self.spot_direction.x).atan()
         };
         let min_angle = direction_angle - 0.5 * self.spot_angle;
-        for step in 1..=self.num_rays {
-            let angle = min_angle + (step as f64 / self.num_rays as f64) * self.spot_angle;
-            let (ydir, xdir) = angle.sin_cos();
-            let sign = self.spot_direction.x.signum();
-            self.rays.push(Ray::from_origin(
-                self.position,
-                V2::new(sign * xdir, sign * ydir),
-            ));
-        }
+        let sign = self.spot_direction.x.signum();
+        (0..self.num_rays)
+            .map(|step| {
+                let angle =
+                    min_angle + ((step as f64 + offset()) / self.num_rays as f64) * self.spot_angle;
+                let (ydir, xdir) = angle.sin_cos();
+                Ray::from_origin(self.position, V2::new(sign * xdir, sign * ydir))
+            })
+            .collect()
     }
 }
 
@@ -271,3 +524,107 @@ impl Rotate for SpotLight {
         self.set_num_rays(None);
     }
 }
+
+/// A beam of parallel rays with a finite width which spreads by `divergence`
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+pub struct Laser {
+    position: P2,
+    color: Color,
+    /// radiant power shared by all rays
+    #[serde(default)]
+    power: Float,
+    /// replaces the rgb part of `color` if set
+    #[serde(default)]
+    temperature: Option<ColorTemperature>,
+    #[serde(skip)]
+    rays: Vec<Ray>,
+    num_rays: usize,
+    pub width: Float,
+    /// full angle between the outermost rays in radians
+    pub divergence: Float,
+    direction: V2,
+}
+
+impl Laser {
+    pub fn new(
+        position: P2,
+        direction: V2,
+        width: Float,
+        divergence: Float,
+        num_rays: usize,
+        color: Color,
+    ) -> Self {
+        let mut light = Laser {
+            position,
+            color,
+            power: num_rays as Float,
+            temperature: None,
+            rays: Vec::new(),
+            num_rays,
+            width,
+            divergence,
+            direction: Laser::beam_direction(&direction),
+        };
+        light.set_num_rays(None);
+        light
+    }
+
+    /// `direction` normalized, along the x axis if it has no length like after a click without
+    /// a drag or is not finite
+    fn beam_direction(direction: &V2) -> V2 {
+        let length = direction.norm();
+        if length.is_finite() && length >= EPSILON {
+            direction / length
+        } else {
+            V2::new(1., 0.)
+        }
+    }
+
+    fn set_color(&mut self, color: Color) {
+        self.color = color;
+    }
+
+    pub fn set_num_rays(&mut self, num_rays: Option<usize>) {
+        if let Some(n) = num_rays {
+            self.num_rays = n;
+        }
+        self.rays = self.generate_rays(|| 0.5);
+    }
+
+    /// `offset` places each ray inside of its stratum, in [0, 1).
+    /// Rays start across the width of the beam and fan out from its center line
+    fn generate_rays(&self, mut offset: impl FnMut() -> Float) -> Vec<Ray> {
+        let direction = Laser::beam_direction(&self.direction);
+        let normal = V2::new(-direction.y, direction.x);
+        (0..self.num_rays)
+            .map(|i| {
+                let t = (i as Float + offset()) / self.num_rays as Float - 0.5;
+                let origin = self.position + normal * (t * self.width);
+                let (sine, cosine) = (t * self.divergence).sin_cos();
+                let dir = direction * cosine + normal * sine;
+                Ray::from_origin(origin, dir)
+            })
+            .collect()
+    }
+}
+
+impl HasOrigin for Laser {
+    fn get_origin(&self) -> P2 {
+        self.position
+    }
+
+    fn set_origin(&mut self, origin: P2) {
+        self.position = origin;
+        self.set_num_rays(None);
+    }
+}
+
+impl Rotate for Laser {
+    fn get_rotation(&self) -> Rot2 {
+        Rot2::rotation_between(&V2::new(1., 0.), &self.direction)
+    }
+    fn set_rotation(&mut self, rotation: &Rot2) {
+        self.direction = rotation.matrix().column(0).clone_owned();
+        self.set_num_rays(None);
+    }
+}
@@ -0,0 +1,253 @@
+use collision2d::geo::*;
+use serde::{Deserialize, Serialize};
+
+use super::{Color, WAVELENGTH_D};
+use std::f64::consts::FRAC_PI_2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    #[serde(default)]
+    pub kind: MaterialKind,
+    /// refractive index at the sodium d-line (587.6 nm)
+    pub refractive_index: Float,
+    #[serde(default)]
+    pub dispersion: Dispersion,
+    /// Beer-Lambert absorption coefficients for red, green and blue per unit of length
+    #[serde(default)]
+    pub absorption: [f32; 3],
+    /// decides which material is used where objects overlap, see `MediaStack`
+    #[serde(default)]
+    pub priority: u32,
+    /// width of the glossy lobe around the specular direction, 0 is a perfectly smooth surface
+    #[serde(default)]
+    pub roughness: Float,
+    /// share of the scattered energy that goes into the lambertian lobe
+    #[serde(default)]
+    pub diffuse: Float,
+    /// number of rays a rough surface scatters into per lobe
+    #[serde(default)]
+    pub scatter_rays: usize,
+}
+
+impl Material {
+    /// only dielectrics enclose a medium that rays can travel through
+    pub fn is_medium(&self) -> bool {
+        matches!(self.kind, MaterialKind::Dielectric)
+    }
+
+    /// whether a `MediaStack` tracks rays inside of the object, dielectrics and filter slabs
+    pub fn has_inside(&self) -> bool {
+        matches!(
+            self.kind,
+            MaterialKind::Dielectric | MaterialKind::Filter { .. }
+        )
+    }
+
+    /// returns the refractive index for the given wavelength in nm.
+    /// rays without a wavelength use the plain `refractive_index`
+    pub fn refractive_index_at(&self, wavelength: Option<Float>) -> Float {
+        match wavelength {
+            Some(wavelength) => {
+                self.refractive_index + self.dispersion.offset_from_d_line(wavelength)
+            }
+            None => self.refractive_index,
+        }
+    }
+
+    /// absorbers pass the rest of the light through unchanged
+    pub fn is_rough(&self) -> bool {
+        !matches!(self.kind, MaterialKind::Absorber { .. })
+            && (self.roughness > 0. || self.diffuse > 0.)
+    }
+
+    /// Splits the `specular` direction into scattered directions and their share of the energy.
+    /// `normal` has to point to the side of the surface the rays leave to,
+    /// directions that would end up behind the surface are dropped.
+    pub fn scatter(&self, specular: &V2, normal: &V2) -> Vec<(V2, f32)> {
+        let num_rays = self.scatter_rays.max(1);
+        let diffuse = self.diffuse.clamp(0., 1.);
+        // evenly spaced angles in (-1, 1)
+        let steps: Vec<Float> = (0..num_rays)
+            .map(|k| (k as Float + 0.5) / num_rays as Float * 2. - 1.)
+            .collect();
+        let mut res = Vec::new();
+
+        // glossy lobe
+        let glossy: Vec<(V2, Float)> = if self.roughness > 0. {
+            let spread = self.roughness * FRAC_PI_2;
+            steps
+                .iter()
+                .map(|step| {
+                    (
+                        Rot2::new(step * spread) * specular,
+                        (-2. * step * step).exp(),
+                    )
+                })
+                .filter(|(direction, _)| direction.dot(normal) > 0.)
+                .collect()
+        } else {
+            vec![(*specular, 1.)]
+        };
+        let glossy_sum: Float = glossy.iter().map(|(_, weight)| weight).sum();
+        if glossy_sum > 0. {
+            for (direction, weight) in glossy {
+                res.push((direction, ((1. - diffuse) * weight / glossy_sum) as f32));
+            }
+        } else if diffuse < 1. {
+            // at grazing incidence the whole lobe can end up behind the surface
+            res.push((*specular, (1. - diffuse) as f32));
+        }
+
+        // lambertian lobe
+        if diffuse > 0. {
+            let lambert: Vec<(V2, Float)> = steps
+                .iter()
+                .map(|step| {
+                    let angle = step * FRAC_PI_2;
+                    (Rot2::new(angle) * normal, angle.cos())
+                })
+                .collect();
+            let lambert_sum: Float = lambert.iter().map(|(_, weight)| weight).sum();
+            for (direction, weight) in lambert {
+                res.push((direction, (diffuse * weight / lambert_sum) as f32));
+            }
+        }
+        res
+    }
+
+    /// attenuates `color` after travelling `path_length` through the material.
+    /// alpha is left untouched like on every other split of a rays color
+    pub fn attenuate(&self, color: Color, path_length: Float) -> Color {
+        let transmit = |ch: usize| (-self.absorption[ch] * path_length as f32).exp();
+        [
+            color[0] * transmit(0),
+            color[1] * transmit(1),
+            color[2] * transmit(2),
+            color[3],
+        ]
+    }
+}
+
+impl Material {
+    /// the material of a perfect mirror, behaves like an object without material until it is
+    /// made rough or diffuse
+    pub fn mirror() -> Self {
+        Material {
+            kind: MaterialKind::Mirror,
+            ..Material::default()
+        }
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            kind: MaterialKind::default(),
+            refractive_index: 1.2,
+            dispersion: Dispersion::default(),
+            absorption: [0.; 3],
+            priority: 0,
+            roughness: 0.,
+            diffuse: 0.,
+            scatter_rays: 8,
+        }
+    }
+}
+
+/// How light interacts with the surface of an object.
+/// Objects without a material are perfect mirrors
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum MaterialKind {
+    /// reflects and refracts according to the fresnel equations
+    #[default]
+    Dielectric,
+    /// reflects all of the incoming light like an object without material,
+    /// but can be rough or diffuse
+    Mirror,
+    /// absorbs the given fraction of the incoming light and lets the rest pass straight through
+    /// like a neutral density screen, nothing is reflected. Fully absorbing ones make baffles,
+    /// apertures and black screens
+    Absorber { absorptance: f32 },
+    /// thin half silvered mirror, reflects `reflectance` and lets the rest pass straight through
+    PartialMirror { reflectance: f32 },
+    /// reflects the given share of each rgb channel and transmits the rest
+    Dichroic { reflect: [f32; 3] },
+    /// band pass filter slab, transmits the given share of each rgb channel and absorbs the rest.
+    /// The pass band is applied once when a ray enters the slab, leaving it passes everything
+    Filter { transmit: [f32; 3] },
+}
+
+impl MaterialKind {
+    /// per channel shares of the light that a thin surface reflects and transmits,
+    /// the rest is absorbed. None for dielectrics which split by the fresnel equations
+    pub fn surface_shares(&self) -> Option<([f32; 3], [f32; 3])> {
+        match self {
+            MaterialKind::Dielectric => None,
+            MaterialKind::Mirror => Some(([1.; 3], [0.; 3])),
+            MaterialKind::Absorber { absorptance } => Some(([0.; 3], [1. - absorptance; 3])),
+            MaterialKind::PartialMirror { reflectance } => {
+                Some(([*reflectance; 3], [1. - reflectance; 3]))
+            }
+            MaterialKind::Dichroic { reflect } => Some((
+                *reflect,
+                [1. - reflect[0], 1. - reflect[1], 1. - reflect[2]],
+            )),
+            MaterialKind::Filter { transmit } => Some(([0.; 3], *transmit)),
+        }
+    }
+}
+
+/// Dispersion models. Both are shifted so that they match the materials
+/// `refractive_index` at the d-line, that way the refractive index slider
+/// keeps working and the model only adds the wavelength dependent part.
+/// wavelengths are given in micrometers inside the formulas
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Default)]
+pub enum Dispersion {
+    #[default]
+    None,
+    /// n(λ) = A + B / λ²
+    Cauchy { b: Float },
+    /// n²(λ) = 1 + Σ Bᵢ λ² / (λ² - Cᵢ)
+    Sellmeier { b: [Float; 3], c: [Float; 3] },
+}
+
+impl Dispersion {
+    /// Cauchy coefficient of BK7 crown glass
+    pub fn cauchy_bk7() -> Self {
+        Dispersion::Cauchy { b: 0.00420 }
+    }
+
+    /// Sellmeier coefficients of BK7 crown glass
+    pub fn sellmeier_bk7() -> Self {
+        Dispersion::Sellmeier {
+            b: [1.039_612_12, 0.231_792_344, 1.010_469_45],
+            c: [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+        }
+    }
+
+    fn offset_from_d_line(&self, wavelength: Float) -> Float {
+        match self {
+            Dispersion::None => 0.,
+            Dispersion::Cauchy { .. } | Dispersion::Sellmeier { .. } => {
+                self.eval(wavelength) - self.eval(WAVELENGTH_D)
+            }
+        }
+    }
+
+    /// evaluates the model without the d-line shift, wavelength in nm
+    fn eval(&self, wavelength: Float) -> Float {
+        let micro_meters = wavelength * 0.001;
+        let l_sq = micro_meters * micro_meters;
+        match self {
+            Dispersion::None => 1.,
+            Dispersion::Cauchy { b } => 1. + b / l_sq,
+            Dispersion::Sellmeier { b, c } => {
+                let mut n_sq: Float = 1.;
+                for (bi, ci) in b.iter().zip(c.iter()) {
+                    n_sq += bi * l_sq / (l_sq - ci);
+                }
+                n_sq.max(0.).sqrt()
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+use crate::light_garden::*;
+
+/// The dielectric objects and filter slabs a ray is currently inside of in the order
+/// they were entered. Where dielectrics overlap the one with the highest `Material::priority`
+/// defines the medium, on equal priority the most recently entered object wins.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MediaStack {
+    indices: Vec<usize>,
+}
+
+impl MediaStack {
+    /// collects every dielectric and filter that contains `p`
+    pub fn at_point(objects: &[Object], p: &P2) -> Self {
+        MediaStack {
+            indices: objects
+                .iter()
+                .enumerate()
+                .filter(|(_, obj)| {
+                    obj.get_material().is_some_and(|m| m.has_inside()) && obj.contains(p)
+                })
+                .map(|(ix, _)| ix)
+                .collect(),
+        }
+    }
+
+    pub fn contains(&self, obj_index: usize) -> bool {
+        self.indices.contains(&obj_index)
+    }
+
+    /// the stack after crossing the boundary of the object at `obj_index`
+    pub fn toggled(&self, obj_index: usize) -> Self {
+        let mut res = self.clone();
+        if res.contains(obj_index) {
+            res.indices.retain(|&ix| ix != obj_index);
+        } else {
+            res.indices.push(obj_index);
+        }
+        res
+    }
+
+    /// returns the object index and material of the medium, None is air
+    pub fn current(&self, objects: &[Object]) -> Option<(usize, Material)> {
+        let mut res: Option<(usize, Material)> = None;
+        for &ix in &self.indices {
+            if let Some(material) = objects[ix].get_material() {
+                if material.is_medium()
+                    && res.is_none_or(|(_, current)| material.priority >= current.priority)
+                {
+                    res = Some((ix, material));
+                }
+            }
+        }
+        res
+    }
+}
@@ -1,32 +1,62 @@
 extern crate nalgebra as na;
 
+pub use bvh::*;
 use collision2d::geo::*;
+pub use density::*;
 pub use drawer::*;
+pub use emission::*;
+pub use energy::*;
 use grid::Grid;
 pub use light::*;
+pub use material::*;
+pub use media::*;
 use na::{Point2, distance};
 pub use object::*;
+pub use profile::*;
+pub use ray_path::*;
 #[cfg(not(target_arch = "wasm32"))]
 use rayon::prelude::*;
+pub use rng::*;
+pub use spectrum::*;
 use std::{
     collections::VecDeque,
     sync::{Arc, Mutex},
 };
 pub use string_mod::*;
+pub use subdivision::*;
 pub use tile_map::*;
+pub use trace_cache::*;
 pub use tracer::*;
 use web_time::Instant;
 use wgpu::BlendState;
+pub use worker::*;
 
+pub mod bvh;
+pub mod density;
 pub mod drawer;
+pub mod emission;
+pub mod energy;
 pub mod grid;
 pub mod light;
+pub mod material;
+pub mod media;
 pub mod object;
+pub mod profile;
+pub mod ray_path;
+pub mod rng;
+pub mod spectrum;
 pub mod string_mod;
+pub mod subdivision;
 pub mod tile_map;
+pub mod trace_cache;
 pub mod tracer;
+pub mod worker;
 /// the maximum from an objects/lights origin at which a DragEvent can move it
 const MOVE_DIST: Float = 0.2;
+/// width of newly drawn lasers
+const LASER_WIDTH: Float = 0.02;
+/// full angle of divergence of newly drawn lasers in radians
+const LASER_DIVERGENCE: Float = 0.002;
 
 pub struct LightGarden {
     pub tracer: Tracer,
@@ -50,6 +80,16 @@ pub struct LightGarden {
     mouse_is_down: bool,
     initial_mouse_down: P2,
     drag_event: Option<DragEvent>,
+    /// trace on a worker thread instead of inside of `draw`
+    background_tracing: bool,
+    worker: Option<TraceWorker>,
+    /// fingerprint of the scene of the last submitted job
+    submitted_fingerprint: Option<u64>,
+    job_generation: u64,
+    /// lines of the newest finished background trace
+    background_lines: Vec<(P2, Color)>,
+    /// what follows the mouse in `Mode::Rotate` if a directional light is selected
+    rotate_handle: RotateHandle,
 }
 
 impl LightGarden {
@@ -95,6 +135,13 @@ impl LightGarden {
             mouse_is_down: false,
             initial_mouse_down: P2::new(0., 0.),
             drag_event: None,
+            // threads are not available on the web
+            background_tracing: cfg!(not(target_arch = "wasm32")),
+            worker: None,
+            submitted_fingerprint: None,
+            job_generation: 0,
+            background_lines: Vec::new(),
+            rotate_handle: RotateHandle::default(),
         };
         #[cfg(not(target_arch = "wasm32"))]
         app.load_from_file("default.ron");
@@ -140,6 +187,11 @@ impl LightGarden {
                     .add_drawing_object(Object::new_mirror(*start, self.mouse_pos));
             }
 
+            Mode::DrawDetectorEnd { start } => {
+                self.tracer
+                    .add_drawing_object(Object::new_detector(*start, self.mouse_pos));
+            }
+
             Mode::DrawCircleEnd { start } => {
                 self.tracer.add_drawing_object(Object::new_circle(
                     *start,
@@ -195,25 +247,78 @@ impl LightGarden {
                     )));
             }
 
+            Mode::DrawLaserStart => {
+                self.tracer.add_drawing_light(Light::Laser(Laser::new(
+                    self.mouse_pos,
+                    V2::new(1., 0.),
+                    LASER_WIDTH,
+                    LASER_DIVERGENCE,
+                    self.num_rays,
+                    self.selected_color,
+                )));
+            }
+
+            Mode::DrawLaserEnd { origin } => {
+                self.tracer.add_drawing_light(Light::Laser(Laser::new(
+                    *origin,
+                    self.mouse_pos - origin,
+                    LASER_WIDTH,
+                    LASER_DIVERGENCE,
+                    self.num_rays,
+                    self.selected_color,
+                )));
+            }
+
             Mode::Rotate => {
                 let mouse_pos = self.mouse_pos;
-                if let Some(obj) = self.get_selected_object() {
+                if let Some(obj) = self.get_selected_object_mut() {
                     obj.y_axis_look_at(&mouse_pos);
                 }
-                if let Some(Light::SpotLight(spot)) = self.get_selected_light() {
+                if let Some(Light::SpotLight(spot)) = self.get_selected_light_mut() {
                     spot.x_axis_look_at(&mouse_pos);
                 }
-                if let Some(Light::DirectionalLight(directional_light)) = self.get_selected_light()
+                if let Some(Light::Laser(laser)) = self.get_selected_light_mut() {
+                    laser.x_axis_look_at(&mouse_pos);
+                }
+                let handle = self.rotate_handle;
+                if let Some(Light::DirectionalLight(directional_light)) =
+                    self.get_selected_light_mut()
                 {
-                    directional_light.y_axis_look_at(&mouse_pos);
+                    match handle {
+                        RotateHandle::Rotation => directional_light.y_axis_look_at(&mouse_pos),
+                        RotateHandle::EmissionAngle => {
+                            directional_light.emission_look_at(&mouse_pos)
+                        }
+                        RotateHandle::Divergence => {
+                            directional_light.divergence_look_at(&mouse_pos)
+                        }
+                    }
+                    let (center, [central, left, right]) = directional_light.emission_handles();
+                    let color = |active: bool| {
+                        if active {
+                            [0.5, 1.0, 1.0, 1.0]
+                        } else {
+                            [0.25, 0.5, 0.5, 1.0]
+                        }
+                    };
+                    for (direction, active) in [
+                        (central, handle == RotateHandle::EmissionAngle),
+                        (left, handle == RotateHandle::Divergence),
+                        (right, handle == RotateHandle::Divergence),
+                    ] {
+                        self.drawer.draw_line_segment(
+                            &LineSegment::from_ab(center, center + direction * MOVE_DIST),
+                            color(active),
+                        );
+                    }
                 }
                 if let Some(ix) = self.selected_object {
                     self.drawer
-                        .draw_selector(&mut self.tracer.index_object(ix).get_aabb(), 0.03);
+                        .draw_selector(&mut self.tracer.get_object(ix).get_aabb(), 0.03);
                     if let Object {
                         object_enum: ObjectE::Ellipse(e),
                         ..
-                    } = self.tracer.index_object(ix)
+                    } = self.tracer.get_object(ix)
                     {
                         let color = [0.5, 1.0, 1.0, 1.0];
                         self.drawer.draw_geo(Geo::GeoEllipse(*e), color);
@@ -281,11 +386,11 @@ impl LightGarden {
             Mode::Selected => {
                 if let Some(ix) = self.selected_object {
                     self.drawer
-                        .draw_selector(&mut self.tracer.index_object(ix).get_aabb(), 0.03);
+                        .draw_selector(&mut self.tracer.get_object(ix).get_aabb(), 0.03);
                     if let Object {
                         object_enum: ObjectE::Ellipse(e),
                         ..
-                    } = self.tracer.index_object(ix)
+                    } = self.tracer.get_object(ix)
                     {
                         self.drawer
                             .draw_geo(Geo::GeoEllipse(*e), [0.5, 1.0, 1.0, 1.0]);
@@ -363,8 +468,8 @@ impl LightGarden {
                             // both objects are the same -> abort
                             self.mode = Mode::Selected;
                         } else {
-                            let geo_a = self.tracer.index_object(current_ix).get_geometry();
-                            let geo_b = self.tracer.index_object(click_ix).get_geometry();
+                            let geo_a = self.tracer.get_object(current_ix).get_geometry();
+                            let geo_b = self.tracer.get_object(click_ix).get_geometry();
                             let geo = match op {
                                 LogicOp::And => geo_a & geo_b,
                                 LogicOp::Or => geo_a | geo_b,
@@ -424,6 +529,25 @@ impl LightGarden {
                 self.mode = Mode::Selecting(None);
             }
 
+            Mode::DrawLaserStart => {
+                self.mode = Mode::DrawLaserEnd {
+                    origin: self.mouse_pos,
+                };
+            }
+
+            Mode::DrawLaserEnd { origin } => {
+                self.tracer.add_drawing_light(Light::Laser(Laser::new(
+                    *origin,
+                    self.mouse_pos - *origin,
+                    LASER_WIDTH,
+                    LASER_DIVERGENCE,
+                    self.num_rays,
+                    self.selected_color,
+                )));
+                self.tracer.finish_drawing_light(false);
+                self.mode = Mode::Selecting(None);
+            }
+
             Mode::DrawDirectionalLightStart => {
                 self.mode = Mode::DrawDirectionalLightEnd {
                     start: self.mouse_pos,
@@ -454,6 +578,19 @@ impl LightGarden {
                 self.mode = Mode::Selecting(None);
             }
 
+            Mode::DrawDetectorStart => {
+                self.mode = Mode::DrawDetectorEnd {
+                    start: self.mouse_pos,
+                };
+            }
+
+            Mode::DrawDetectorEnd { start } => {
+                self.tracer
+                    .add_drawing_object(Object::new_detector(*start, self.mouse_pos));
+                self.tracer.finish_drawing_object(false);
+                self.mode = Mode::Selecting(None);
+            }
+
             Mode::DrawCircleStart => {
                 self.mode = Mode::DrawCircleEnd {
                     start: self.mouse_pos,
@@ -554,15 +691,15 @@ impl LightGarden {
                     }
                 }
                 Mode::Moving => {
-                    if let Some(obj) = self.get_selected_object() {
+                    if let Some(obj) = self.get_selected_object_mut() {
                         obj.set_origin(drag_event.end);
                     }
-                    if let Some(light) = self.get_selected_light() {
+                    if let Some(light) = self.get_selected_light_mut() {
                         light.set_origin(drag_event.end);
                     }
                 }
                 Mode::EditObject => {
-                    if let Some(obj) = self.get_selected_object() {
+                    if let Some(obj) = self.get_selected_object_mut() {
                         match obj.object_enum {
                             ObjectE::CurvedMirror(ref mut cm) => {
                                 let mut min_distance = Float::MAX;
@@ -592,11 +729,14 @@ impl LightGarden {
         self.selected_light = None;
     }
 
-    pub fn get_selected_object(&mut self) -> Option<&mut Object> {
+    pub fn get_selected_object(&self) -> Option<&Object> {
+        Some(self.tracer.get_object(self.selected_object?))
+    }
+
+    /// for edits of the selected object, the cached geometry and the bvh follow them
+    pub fn get_selected_object_mut(&mut self) -> Option<&mut Object> {
         if let Some(ix) = self.selected_object {
-            if self.tracer.tile_map_enabled() {
-                self.tracer.obj_changed(ix);
-            }
+            self.tracer.obj_changed(ix);
             let obj_ref = self.tracer.index_object(ix);
             Some(obj_ref)
         } else {
@@ -604,7 +744,12 @@ impl LightGarden {
         }
     }
 
-    pub fn get_selected_light(&mut self) -> Option<&mut Light> {
+    pub fn get_selected_light(&self) -> Option<&Light> {
+        Some(self.tracer.get_light(self.selected_light?))
+    }
+
+    /// for edits of the selected light
+    pub fn get_selected_light_mut(&mut self) -> Option<&mut Light> {
         if let Some(ix) = self.selected_light {
             let light_ref = self.tracer.index_light(ix);
             Some(light_ref)
@@ -613,6 +758,23 @@ impl LightGarden {
         }
     }
 
+    /// Enters `Mode::Rotate`. Directional lights have handles for the rotation,
+    /// the emission angle and the divergence, rotating again switches to the next one
+    pub fn rotate(&mut self) {
+        if self.mode == Mode::Rotate
+            && let Some(Light::DirectionalLight(_)) = self.get_selected_light()
+        {
+            self.rotate_handle = self.rotate_handle.next();
+        } else {
+            self.rotate_handle = RotateHandle::Rotation;
+        }
+        self.mode = Mode::Rotate;
+    }
+
+    pub fn rotate_handle(&self) -> RotateHandle {
+        self.rotate_handle
+    }
+
     pub fn delete_selected(&mut self) {
         if let Some(ix) = self.selected_light {
             self.tracer.remove_light(ix);
@@ -632,13 +794,13 @@ impl LightGarden {
 
     pub fn copy_selected(&mut self) {
         if let Some(ix) = self.selected_object {
-            let mut cpy = self.tracer.index_object(ix).clone();
+            let mut cpy = self.tracer.get_object(ix).clone();
             let pos = cpy.get_origin();
             cpy.set_origin(pos + V2::new(0.05, 0.05));
             self.tracer.push_object(cpy);
         }
         if let Some(ix) = self.selected_light {
-            let mut cpy = self.tracer.index_light(ix).clone();
+            let mut cpy = self.tracer.get_light(ix).clone();
             let pos = cpy.get_origin();
             cpy.set_origin(pos + V2::new(0.05, 0.05));
             self.tracer.push_light(cpy);
@@ -647,14 +809,14 @@ impl LightGarden {
 
     pub fn mirror_on_x_axis_selected(&mut self) {
         if let Some(ix) = self.selected_object {
-            let obj = self.tracer.index_object(ix).mirror_y();
+            let obj = self.tracer.get_object(ix).mirror_y();
             self.tracer.push_object(obj);
         }
     }
 
     pub fn mirror_on_y_axis_selected(&mut self) {
         if let Some(ix) = self.selected_object {
-            let obj = self.tracer.index_object(ix).mirror_x();
+            let obj = self.tracer.get_object(ix).mirror_x();
             self.tracer.push_object(obj);
         }
     }
@@ -674,6 +836,85 @@ impl LightGarden {
             println!("render_to_texture toggled: {render_to_texture}");
             self.render_to_texture = render_to_texture;
             self.recreate_pipelines = true;
+            // accumulation and the density image need the render texture
+            if !render_to_texture {
+                self.tracer.progressive = false;
+                self.tracer.render_mode = RenderMode::Lines;
+            }
+        }
+    }
+
+    pub fn set_progressive(&mut self, progressive: bool) {
+        if progressive != self.tracer.progressive {
+            self.tracer.progressive = progressive;
+            self.tracer.restart_accumulation();
+            if progressive {
+                self.set_render_to_texture(true);
+            }
+        }
+    }
+
+    pub fn background_tracing(&self) -> bool {
+        self.background_tracing
+    }
+
+    /// the worker thread is stopped when background tracing is disabled
+    pub fn set_background_tracing(&mut self, background_tracing: bool) {
+        self.background_tracing = background_tracing;
+        if !background_tracing {
+            self.worker = None;
+            self.submitted_fingerprint = None;
+        }
+    }
+
+    /// whether the background worker has not finished tracing the current scene yet
+    pub fn is_tracing(&self) -> bool {
+        self.worker.as_ref().is_some_and(TraceWorker::is_tracing)
+    }
+
+    /// Submits the scene to the background worker if it changed and returns the lines
+    /// of the newest finished trace, the previous ones until the current scene is traced
+    fn trace_in_background(&mut self) -> Vec<(P2, Color)> {
+        let worker = self
+            .worker
+            .get_or_insert_with(|| TraceWorker::new(&self.tracer.canvas_bounds));
+        let fingerprint = self.tracer.job_fingerprint();
+        if self.submitted_fingerprint != Some(fingerprint) {
+            self.submitted_fingerprint = Some(fingerprint);
+            self.job_generation += 1;
+            worker.submit(self.tracer.trace_job(self.job_generation));
+            // nothing traces on this tracer, the tile map overlay has to follow the edits
+            self.tracer.update_acceleration();
+        }
+        if let Some(output) = worker.poll() {
+            self.background_lines = self.tracer.apply_output(output);
+        }
+        self.background_lines.clone()
+    }
+
+    pub fn set_render_mode(&mut self, render_mode: RenderMode) {
+        if render_mode != self.tracer.render_mode {
+            self.tracer.render_mode = render_mode;
+            self.tracer.restart_accumulation();
+            if render_mode == RenderMode::Density {
+                self.set_render_to_texture(true);
+            }
+        }
+    }
+
+    /// saves the tone mapped density image, the format is chosen by the extension of `path`
+    pub fn save_density_image(&self, path: &str) {
+        let density = &self.tracer.density;
+        let scale = 1. / self.tracer.accumulated_passes().max(1) as f32;
+        if let Err(e) = image::save_buffer(
+            path,
+            &density.to_rgb8(scale),
+            density.get_width() as u32,
+            density.get_height() as u32,
+            image::ColorType::Rgb8,
+        ) {
+            println!("Error: could not save the density image");
+            println!("Message: {e}");
         }
     }
 
@@ -686,12 +927,63 @@ impl LightGarden {
             RenderResult {
                 lines,
                 triangles: Vec::new(),
+                clear: true,
+                accumulated_passes: 0,
+                density: None,
+            }
+        } else if self.tracer.render_mode == RenderMode::Density {
+            if self.recreate_pipelines {
+                self.tracer.restart_accumulation();
+            }
+            self.tracer.trace_density();
+            let scale = 1. / self.tracer.accumulated_passes().max(1) as f32;
+            // the image is replaced every frame so helpers can be drawn on top
+            let mut lines = self.tracer.helper_lines();
+            lines.append(&mut self.drawer.get_lines());
+            RenderResult {
+                lines,
+                triangles: self.drawer.get_triangles(),
+                clear: false,
+                accumulated_passes: 0,
+                density: Some(self.tracer.density.tone_mapped(scale)),
+            }
+        } else if self.tracer.progressive {
+            if self.recreate_pipelines {
+                self.tracer.restart_accumulation();
+            }
+            let lines = self.tracer.trace_all();
+            // the selection markers and other helpers would add up in the accumulated image
+            self.drawer.get_lines();
+            self.drawer.get_triangles();
+            RenderResult {
+                lines,
+                triangles: Vec::new(),
+                clear: self.tracer.render_texture_cleared(),
+                accumulated_passes: self.tracer.accumulated_passes(),
+                density: None,
+            }
+        } else if self.background_tracing {
+            let mut lines = self.trace_in_background();
+            lines.extend(self.tracer.helper_lines());
+            lines.append(&mut self.drawer.get_lines());
+            RenderResult {
+                lines,
+                triangles: self.drawer.get_triangles(),
+                clear: true,
+                accumulated_passes: 0,
+                density: None,
             }
         } else {
             let mut lines = self.tracer.trace_all();
             lines.append(&mut self.drawer.get_lines());
             let triangles = self.drawer.get_triangles();
-            RenderResult { lines, triangles }
+            RenderResult {
+                lines,
+                triangles,
+                clear: true,
+                accumulated_passes: 0,
+                density: None,
+            }
         }
     }
 
@@ -717,6 +1009,7 @@ impl LightGarden {
             }
             for light in lights.iter_mut() {
                 light.set_num_rays(None);
+                light.migrate_power();
             }
             for light in lights.drain(..) {
                 self.tracer.push_light(light);
@@ -734,6 +1027,8 @@ pub enum Mode {
     EditObject,
     DrawMirrorStart,
     DrawMirrorEnd { start: P2 },
+    DrawDetectorStart,
+    DrawDetectorEnd { start: P2 },
     DrawCircleStart,
     DrawCircleEnd { start: P2 },
     DrawRectStart,
@@ -748,11 +1043,32 @@ pub enum Mode {
     DrawSpotLightEnd { origin: P2 },
     DrawDirectionalLightStart,
     DrawDirectionalLightEnd { start: P2 },
+    DrawLaserStart,
+    DrawLaserEnd { origin: P2 },
     SelectTile,
     TileSelected { tile: Tile },
     StringMod,
 }
 
+/// The parameter of a directional light that follows the mouse in `Mode::Rotate`
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum RotateHandle {
+    #[default]
+    Rotation,
+    EmissionAngle,
+    Divergence,
+}
+
+impl RotateHandle {
+    pub fn next(self) -> Self {
+        match self {
+            RotateHandle::Rotation => RotateHandle::EmissionAngle,
+            RotateHandle::EmissionAngle => RotateHandle::Divergence,
+            RotateHandle::Divergence => RotateHandle::Rotation,
+        }
+    }
+}
+
 use std::fmt::{Display, Formatter, Result};
 
 impl Display for Mode {
@@ -766,6 +1082,8 @@ impl Display for Mode {
             Mode::EditObject => write!(f, "EditObject"),
             Mode::DrawMirrorStart => write!(f, "DrawMirrorStart"),
             Mode::DrawMirrorEnd { start: _ } => write!(f, "DrawMirrorEnd"),
+            Mode::DrawDetectorStart => write!(f, "DrawDetectorStart"),
+            Mode::DrawDetectorEnd { .. } => write!(f, "DrawDetectorEnd"),
             Mode::DrawCircleStart => write!(f, "DrawCircleStart"),
             Mode::DrawCircleEnd { .. } => write!(f, "DrawCircleEnd"),
             Mode::DrawRectStart => write!(f, "DrawRectStart"),
@@ -777,6 +1095,8 @@ impl Display for Mode {
             Mode::DrawSpotLightEnd { .. } => write!(f, "DrawSpotLightEnd"),
             Mode::DrawDirectionalLightStart => write!(f, "DrawDirectionalLightStart"),
             Mode::DrawDirectionalLightEnd { .. } => write!(f, "DrawDirectionalLightEnd"),
+            Mode::DrawLaserStart => write!(f, "DrawLaserStart"),
+            Mode::DrawLaserEnd { .. } => write!(f, "DrawLaserEnd"),
             Mode::SelectTile => write!(f, "SelectTile"),
             Mode::TileSelected { .. } => write!(f, "TileSelected"),
             Mode::StringMod => write!(f, "StringMod"),
@@ -796,4 +1116,11 @@ pub struct DragEvent {
 pub struct RenderResult {
     pub lines: Vec<(P2, Color)>,
     pub triangles: Vec<(P2, Color)>,
+    /// clear the render texture before drawing, otherwise the lines are added to it
+    pub clear: bool,
+    /// number of passes summed up in the render texture, 0 if it only holds the current frame
+    pub accumulated_passes: u32,
+    /// tone mapped image of `Tracer::density` which replaces the render texture,
+    /// the lines are drawn on top of it
+    pub density: Option<Vec<[f32; 4]>>,
 }
@@ -1,7 +1,8 @@
 use collision2d::geo::*;
 use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
 
-use super::Color;
+use super::{Color, Emission, Material, color_energy};
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ObjectE {
@@ -13,6 +14,7 @@ pub enum ObjectE {
     ConvexPolygon(ConvexPolygon),
     Ellipse(Ellipse),
     Geo(Geo),
+    Detector(Detector),
 }
 
 impl ObjectE {
@@ -45,13 +47,32 @@ impl ObjectE {
     pub fn new_geo(geo: Geo) -> Self {
         ObjectE::Geo(geo)
     }
+    pub fn new_detector(a: P2, b: P2) -> Self {
+        ObjectE::Detector(Detector::new(LineSegment::from_ab(a, b)))
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Object {
     pub object_enum: ObjectE,
     pub material_opt: Option<Material>,
+    /// turns the object into an area light
+    #[serde(default)]
+    pub emission: Option<Emission>,
     pub moved: bool,
+    #[serde(skip)]
+    geometry: GeometryCache,
+}
+
+/// The geometry and AABB of an object, computed on first use.
+/// Always compares equal so it does not take part in comparing objects
+#[derive(Debug, Clone, Default)]
+pub struct GeometryCache(OnceLock<(Geo, Aabb)>);
+
+impl PartialEq for GeometryCache {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
 }
 
 impl Object {
@@ -59,58 +80,102 @@ impl Object {
         Object {
             object_enum: ObjectE::new_mirror(a, b),
             material_opt: None,
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_curved_mirror(cubic: &CubicBezier) -> Self {
         Object {
             object_enum: ObjectE::new_curved_mirror(cubic),
             material_opt: None,
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_circle(origin: P2, radius: Float) -> Self {
         Object {
             object_enum: ObjectE::new_circle(origin, radius),
             material_opt: Some(Material::default()),
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_rect(origin: P2, width: Float, height: Float) -> Self {
         Object {
             object_enum: ObjectE::new_rect(origin, width, height),
             material_opt: Some(Material::default()),
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_lens(origin: P2, radius: Float, distance: Float) -> Self {
         Object {
             object_enum: ObjectE::new_lens(origin, radius, distance),
             material_opt: Some(Material::default()),
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_convex_polygon(points: &[P2]) -> Self {
         Object {
             object_enum: ObjectE::new_convex_polygon(points),
             material_opt: Some(Material::default()),
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_ellipse(origin: P2, a: Float, b: Float) -> Self {
         Object {
             object_enum: ObjectE::new_ellipse(origin, a, b),
             material_opt: Some(Material::default()),
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     pub fn new_geo(geo: Geo) -> Self {
         Object {
             object_enum: ObjectE::Geo(geo),
             material_opt: Some(Material::default()),
+            emission: None,
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
+    pub fn new_detector(a: P2, b: P2) -> Self {
+        Object {
+            object_enum: ObjectE::new_detector(a, b),
+            material_opt: None,
+            emission: None,
+            moved: true,
+            geometry: GeometryCache::default(),
+        }
+    }
+    /// The geometry used for intersection tests, only built again after
+    /// `invalidate_geometry`. Unlike `get_geometry` it does not clone
+    pub fn cached_geometry(&self) -> &Geo {
+        &self.cached().0
+    }
+    pub fn cached_aabb(&self) -> &Aabb {
+        &self.cached().1
+    }
+    /// has to be called after `object_enum` was edited
+    pub fn invalidate_geometry(&mut self) {
+        self.geometry = GeometryCache::default();
+    }
+    fn cached(&self) -> &(Geo, Aabb) {
+        self.geometry.0.get_or_init(|| {
+            let geo = self.object_enum.get_geometry();
+            let aabb = geo.get_aabb();
+            (geo, aabb)
+        })
+    }
     pub fn get_material(&self) -> Option<Material> {
         self.material_opt
     }
@@ -130,6 +195,7 @@ impl HasOrigin for ObjectE {
             ObjectE::ConvexPolygon(cp) => cp.get_origin(),
             ObjectE::Ellipse(e) => e.get_origin(),
             ObjectE::Geo(g) => g.get_origin(),
+            ObjectE::Detector(d) => d.line_segment.get_origin(),
         }
     }
     fn set_origin(&mut self, origin: P2) {
@@ -142,6 +208,7 @@ impl HasOrigin for ObjectE {
             ObjectE::ConvexPolygon(cp) => cp.set_origin(origin),
             ObjectE::Ellipse(e) => e.set_origin(origin),
             ObjectE::Geo(g) => g.set_origin(origin),
+            ObjectE::Detector(d) => d.line_segment.set_origin(origin),
         }
     }
 }
@@ -152,6 +219,7 @@ impl HasOrigin for Object {
     }
     fn set_origin(&mut self, origin: P2) {
         self.moved = true;
+        self.invalidate_geometry();
         self.object_enum.set_origin(origin);
     }
 }
@@ -167,6 +235,7 @@ impl Rotate for ObjectE {
             ObjectE::ConvexPolygon(cp) => cp.get_rotation(),
             ObjectE::Ellipse(e) => e.get_rotation(),
             ObjectE::Geo(g) => g.get_rotation(),
+            ObjectE::Detector(d) => d.line_segment.get_rotation(),
         }
     }
     fn set_rotation(&mut self, rotation: &Rot2) {
@@ -179,6 +248,7 @@ impl Rotate for ObjectE {
             ObjectE::ConvexPolygon(cp) => cp.set_rotation(rotation),
             ObjectE::Ellipse(e) => e.set_rotation(rotation),
             ObjectE::Geo(g) => g.set_rotation(rotation),
+            ObjectE::Detector(d) => d.line_segment.set_rotation(rotation),
         }
     }
 }
@@ -189,6 +259,7 @@ impl Rotate for Object {
     }
     fn set_rotation(&mut self, rotation: &Rot2) {
         self.moved = true;
+        self.invalidate_geometry();
         self.object_enum.set_rotation(rotation);
     }
 }
@@ -204,6 +275,7 @@ impl Mirror for ObjectE {
             ObjectE::ConvexPolygon(cp) => ObjectE::ConvexPolygon(cp.mirror_x()),
             ObjectE::Ellipse(e) => ObjectE::Ellipse(e.mirror_x()),
             ObjectE::Geo(g) => ObjectE::Geo(g.mirror_x()),
+            ObjectE::Detector(d) => ObjectE::Detector(d.mirror_x()),
         }
     }
     fn mirror_y(&self) -> Self {
@@ -216,6 +288,7 @@ impl Mirror for ObjectE {
             ObjectE::ConvexPolygon(cp) => ObjectE::ConvexPolygon(cp.mirror_y()),
             ObjectE::Ellipse(e) => ObjectE::Ellipse(e.mirror_y()),
             ObjectE::Geo(g) => ObjectE::Geo(g.mirror_y()),
+            ObjectE::Detector(d) => ObjectE::Detector(d.mirror_y()),
         }
     }
 }
@@ -225,14 +298,18 @@ impl Mirror for Object {
         Object {
             object_enum: self.object_enum.mirror_x(),
             material_opt: self.material_opt,
+            emission: self.emission.clone(),
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
     fn mirror_y(&self) -> Self {
         Object {
             object_enum: self.object_enum.mirror_y(),
             material_opt: self.material_opt,
+            emission: self.emission.clone(),
             moved: true,
+            geometry: GeometryCache::default(),
         }
     }
 }
@@ -248,6 +325,7 @@ impl Contains for ObjectE {
             ObjectE::ConvexPolygon(cp) => cp.contains(p),
             ObjectE::Ellipse(e) => e.contains(p),
             ObjectE::Geo(g) => g.contains(p),
+            ObjectE::Detector(_) => false,
         }
     }
 }
@@ -269,6 +347,7 @@ impl Distance for ObjectE {
             ObjectE::ConvexPolygon(cp) => cp.distance(p),
             ObjectE::Ellipse(e) => e.distance(p),
             ObjectE::Geo(g) => g.distance(p),
+            ObjectE::Detector(d) => d.line_segment.distance(p),
         }
     }
 }
@@ -290,6 +369,7 @@ impl HasGeometry for ObjectE {
             ObjectE::ConvexPolygon(cp) => Geo::GeoConvexPolygon(cp.clone()),
             ObjectE::Ellipse(e) => Geo::GeoEllipse(*e),
             ObjectE::Geo(g) => g.clone(),
+            ObjectE::Detector(d) => d.get_geometry(),
         }
     }
 }
@@ -433,15 +513,94 @@ impl HasGeometry for Lens {
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
-pub struct Material {
-    pub refractive_index: Float,
+/// A line segment that records the rays hitting it, binned along its length.
+/// The bins are refilled on every trace
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Detector {
+    pub line_segment: LineSegment,
+    pub num_bins: usize,
+    /// let rays continue straight through instead of absorbing them
+    pub pass_through: bool,
+    #[serde(skip)]
+    pub bins: Vec<DetectorBin>,
 }
 
-impl Default for Material {
-    fn default() -> Self {
-        Material {
-            refractive_index: 1.2,
+impl Detector {
+    pub fn new(line_segment: LineSegment) -> Self {
+        let mut detector = Detector {
+            line_segment,
+            num_bins: 64,
+            pass_through: false,
+            bins: Vec::new(),
+        };
+        detector.clear_bins();
+        detector
+    }
+
+    pub fn clear_bins(&mut self) {
+        self.bins = vec![DetectorBin::default(); self.num_bins.max(1)];
+    }
+
+    /// adds a ray hitting the detector at `p` to its bin
+    pub fn record(&mut self, p: &P2, color: &Color) {
+        if self.bins.len() != self.num_bins.max(1) {
+            self.clear_bins();
+        }
+        let a = self.line_segment.get_a();
+        let ab = self.line_segment.get_b() - a;
+        let t = ((p - a).dot(&ab) / ab.norm_squared()).clamp(0., 1.);
+        let ix = ((t * self.bins.len() as Float) as usize).min(self.bins.len() - 1);
+        let bin = &mut self.bins[ix];
+        bin.hits += 1;
+        bin.color[0] += color[0];
+        bin.color[1] += color[1];
+        bin.color[2] += color[2];
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("bin,position,hits,red,green,blue,intensity\n");
+        for (ix, bin) in self.bins.iter().enumerate() {
+            // center of the bin relative to the detectors length
+            let position = (ix as Float + 0.5) / self.bins.len() as Float;
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{}\n",
+                ix,
+                position,
+                bin.hits,
+                bin.color[0],
+                bin.color[1],
+                bin.color[2],
+                color_energy(&bin.color)
+            ));
+        }
+        csv
+    }
+}
+
+impl Mirror for Detector {
+    fn mirror_x(&self) -> Self {
+        Detector {
+            line_segment: self.line_segment.mirror_x(),
+            ..self.clone()
+        }
+    }
+    fn mirror_y(&self) -> Self {
+        Detector {
+            line_segment: self.line_segment.mirror_y(),
+            ..self.clone()
         }
     }
 }
+
+impl HasGeometry for Detector {
+    fn get_geometry(&self) -> Geo {
+        Geo::GeoLineSegment(self.line_segment)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct DetectorBin {
+    pub hits: usize,
+    /// accumulated rgb of all hits
+    pub color: [f32; 3],
+}
@@ -0,0 +1,215 @@
+use collision2d::geo::*;
+use serde::{Deserialize, Serialize};
+use std::f64::consts::PI;
+
+/// Intensity of a light over the angle between a ray and the lights axis
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Default)]
+pub enum AngularProfile {
+    #[default]
+    Uniform,
+    /// cos(angle)^exponent, nothing behind the light
+    Cosine { exponent: Float },
+    /// gaussian with the standard deviation `sigma` in radians
+    Gaussian { sigma: Float },
+    /// full intensity up to `inner`, fades out smoothly until `outer`, both in radians
+    SoftSpot { inner: Float, outer: Float },
+    /// piecewise linear between the samples, angles in degrees in ascending order.
+    /// Angles beyond the last sample keep its intensity
+    Tabulated {
+        angles: Vec<Float>,
+        values: Vec<Float>,
+    },
+}
+
+impl AngularProfile {
+    /// relative intensity at `angle` radians off the axis
+    pub fn intensity(&self, angle: Float) -> Float {
+        let angle = angle.abs().min(PI);
+        match self {
+            AngularProfile::Uniform => 1.,
+            AngularProfile::Cosine { exponent } => angle.cos().max(0.).powf(*exponent),
+            AngularProfile::Gaussian { sigma } => {
+                let t = angle / sigma.max(EPSILON);
+                (-0.5 * t * t).exp()
+            }
+            AngularProfile::SoftSpot { inner, outer } => {
+                if angle <= *inner {
+                    1.
+                } else if angle >= *outer {
+                    0.
+                } else {
+                    // smoothstep from 1 to 0
+                    let t = (angle - inner) / (outer - inner);
+                    1. - t * t * (3. - 2. * t)
+                }
+            }
+            AngularProfile::Tabulated { angles, values } => {
+                let degrees = angle.to_degrees();
+                let len = angles.len().min(values.len());
+                if len == 0 {
+                    return 1.;
+                }
+                let ix = angles[..len].partition_point(|a| *a <= degrees);
+                let value = if ix == 0 {
+                    values[0]
+                } else if ix == len {
+                    values[len - 1]
+                } else {
+                    let t = (degrees - angles[ix - 1]) / (angles[ix] - angles[ix - 1]);
+                    values[ix - 1] * (1. - t) + values[ix] * t
+                };
+                value.max(0.)
+            }
+        }
+    }
+
+    /// Parses `data` as an IES LM-63 file if it has a `TILT=` line and as a table otherwise
+    pub fn parse(data: &str) -> Result<Self, String> {
+        if data
+            .lines()
+            .any(|line| line.trim_start().starts_with("TILT="))
+        {
+            AngularProfile::parse_ies(data)
+        } else {
+            AngularProfile::parse_table(data)
+        }
+    }
+
+    /// Parses a table with one `angle intensity` pair per line, angles in degrees.
+    /// The values can be separated by whitespace, commas or semicolons,
+    /// empty lines and lines starting with `#` are skipped.
+    /// Intensities are normalized to a maximum of 1
+    pub fn parse_table(data: &str) -> Result<Self, String> {
+        let mut rows: Vec<(Float, Float)> = Vec::new();
+        for (line_ix, line) in data.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let numbers: Vec<Float> = line
+                .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+                .filter(|s| !s.is_empty())
+                .map(|s| s.parse::<Float>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("line {}: {e}", line_ix + 1))?;
+            match numbers[..] {
+                [angle, value] => rows.push((angle, value)),
+                _ => {
+                    return Err(format!(
+                        "line {}: expected angle and intensity",
+                        line_ix + 1
+                    ));
+                }
+            }
+        }
+        AngularProfile::from_rows(rows)
+    }
+
+    /// Parses the candela values of the first horizontal plane of an IES LM-63 photometric file.
+    /// The vertical angles are taken as the angles to the lights axis, the other planes,
+    /// the tilt data and the photometric type are ignored.
+    /// Intensities are normalized to a maximum of 1
+    pub fn parse_ies(data: &str) -> Result<Self, String> {
+        let mut lines = data.lines();
+        // the keyword lines in front of the tilt line only describe the luminaire
+        let tilt = lines
+            .by_ref()
+            .find_map(|line| line.trim().strip_prefix("TILT="))
+            .ok_or("missing TILT line")?
+            .trim()
+            .to_string();
+        let numbers: Vec<Float> = lines
+            .flat_map(|line| line.split(|c: char| c.is_whitespace() || c == ','))
+            .filter(|s| !s.is_empty())
+            .map(|s| s.parse::<Float>().map_err(|e| format!("{s}: {e}")))
+            .collect::<Result<_, _>>()?;
+        let mut rest = &numbers[..];
+        if tilt == "INCLUDE" {
+            // lamp to luminaire geometry and the number of tilt angles, then the angles and factors
+            let tilt_header = take_numbers(&mut rest, 2)?;
+            take_numbers(&mut rest, 2 * ies_count(tilt_header[1])?)?;
+        }
+        // lamps, lumens per lamp, candela multiplier, number of vertical and horizontal angles,
+        // photometric type, units, width, length, height, ballast factor, future use, input watts
+        let header = take_numbers(&mut rest, 13)?;
+        let num_vertical = ies_count(header[3])?;
+        let num_horizontal = ies_count(header[4])?;
+        let angles = take_numbers(&mut rest, num_vertical)?;
+        take_numbers(&mut rest, num_horizontal)?;
+        let values = take_numbers(&mut rest, num_vertical)?;
+        let rows = angles.iter().copied().zip(values.iter().copied()).collect();
+        AngularProfile::from_rows(rows)
+    }
+
+    /// the tabulated profile of the `(angle, intensity)` rows, sorted and normalized
+    fn from_rows(mut rows: Vec<(Float, Float)>) -> Result<Self, String> {
+        if rows.is_empty() {
+            return Err("no samples found".to_string());
+        }
+        rows.sort_by(|a, b| a.0.total_cmp(&b.0));
+        let max = rows.iter().map(|(_, v)| *v).fold(0., Float::max);
+        let scale = if max > 0. { 1. / max } else { 1. };
+        Ok(AngularProfile::Tabulated {
+            angles: rows.iter().map(|(a, _)| *a).collect(),
+            values: rows.iter().map(|(_, v)| v * scale).collect(),
+        })
+    }
+}
+
+/// splits the first `count` numbers off of `rest`
+fn take_numbers<'a>(rest: &mut &'a [Float], count: usize) -> Result<&'a [Float], String> {
+    if rest.len() < count {
+        return Err("unexpected end of file".to_string());
+    }
+    let (numbers, tail) = rest.split_at(count);
+    *rest = tail;
+    Ok(numbers)
+}
+
+/// a number of entries in an IES file
+fn ies_count(number: Float) -> Result<usize, String> {
+    if number < 0. || number.fract() != 0. {
+        return Err(format!("invalid count {number}"));
+    }
+    Ok(number as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const IES: &str = "IESNA:LM-63-2002
+[TEST] spot
+[MANUFAC] none
+TILT=INCLUDE
+1
+3
+0 45 90
+1.0 0.9 0.8
+1 1000 1.0 3 2 1 2 0.1 0.1 0.0
+1.0 1.0 50
+0, 45, 90
+0 90
+200 100
+0
+100 100 100
+";
+
+    #[test]
+    fn parse_ies_first_plane() {
+        assert_eq!(
+            AngularProfile::parse(IES),
+            Ok(AngularProfile::Tabulated {
+                angles: vec![0., 45., 90.],
+                values: vec![1., 0.5, 0.],
+            })
+        );
+    }
+
+    #[test]
+    fn parse_ies_truncated() {
+        // the candela values are missing
+        let truncated = &IES[..IES.find("200 100").unwrap()];
+        assert!(AngularProfile::parse_ies(truncated).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+use crate::light_garden::*;
+
+/// Where the rays of a `RayPath` come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PathSource {
+    /// index of the light, the light that is being drawn comes after the others
+    Light(usize),
+    /// index of an emissive object, the object that is being drawn comes after the others
+    Object(usize),
+}
+
+/// A straight piece of a traced ray up to its next interaction or the canvas bounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PathSegment {
+    pub start: P2,
+    pub end: P2,
+    pub start_color: Color,
+    /// the color after the absorption along the segment
+    pub end_color: Color,
+    /// the object hit at `end`, None if the segment leaves the canvas
+    pub hit: Option<usize>,
+    /// surface normal at the hit
+    pub normal: Option<Normal>,
+    /// angle between the ray and the surface normal at the hit in radians
+    pub incidence_angle: Option<Float>,
+    /// the object whose material the segment runs through, None outside of all objects
+    pub medium: Option<usize>,
+    /// number of interactions before the segment, 0 for the segment leaving the source
+    pub depth: u32,
+    /// None if dispersion is disabled
+    pub wavelength: Option<Float>,
+    /// energy of the rays leaving the hit on the side the segment came from
+    pub reflected_energy: f32,
+    /// energy of the rays leaving the hit on the other side
+    pub transmitted_energy: f32,
+}
+
+impl PathSegment {
+    /// the segment as a pair of line vertices like the render line list
+    pub fn line(&self) -> [(P2, Color); 2] {
+        [(self.start, self.start_color), (self.end, self.end_color)]
+    }
+
+    /// energy lost on the way through the medium
+    pub fn medium_absorbed(&self) -> f32 {
+        color_energy(&self.start_color) - color_energy(&self.end_color)
+    }
+
+    /// energy the surface at the hit absorbed, 0 at dielectric boundaries
+    pub fn surface_absorbed(&self) -> f32 {
+        match self.hit {
+            Some(_) => {
+                color_energy(&self.end_color) - self.reflected_energy - self.transmitted_energy
+            }
+            None => 0.,
+        }
+    }
+}
+
+/// All segments traced for a single ray of a source, including every spectral sample
+/// and the reflected and refracted branches
+#[derive(Debug, Clone, PartialEq)]
+pub struct RayPath {
+    pub source: PathSource,
+    /// index of the ray among the rays of the source in this trace
+    pub ray: usize,
+    pub segments: Vec<PathSegment>,
+    /// energy of the ray summed over its spectral samples
+    pub emitted: f32,
+    /// energy that left the canvas
+    pub escaped: f32,
+    /// energy of branches that fell below `Tracer::cutoff_color`
+    pub cutoff: f32,
+    /// energy of branches that were still going after `Tracer::max_bounce`
+    pub max_bounce: f32,
+    /// left out by the frame budget, the segments are missing or out of date
+    pub pending: bool,
+}
+
+impl RayPath {
+    pub fn new(source: PathSource, ray: usize) -> Self {
+        RayPath {
+            source,
+            ray,
+            segments: Vec::new(),
+            emitted: 0.,
+            escaped: 0.,
+            cutoff: 0.,
+            max_bounce: 0.,
+            pending: false,
+        }
+    }
+
+    /// appends the segments as pairs of line vertices to `lines`
+    pub fn push_lines(&self, lines: &mut Vec<(P2, Color)>) {
+        lines.extend(self.segments.iter().flat_map(PathSegment::line));
+    }
+
+    /// the segments that end on a detector as (object index, hit point, color)
+    pub fn detector_hits<'a>(
+        &'a self,
+        objects: &'a [Object],
+    ) -> impl Iterator<Item = (usize, P2, Color)> + 'a {
+        self.segments.iter().filter_map(|segment| {
+            let ix = segment.hit?;
+            matches!(objects.get(ix)?.object_enum, ObjectE::Detector(_)).then_some((
+                ix,
+                segment.end,
+                segment.end_color,
+            ))
+        })
+    }
+
+    /// whether a segment of the path touches one of `regions`
+    pub fn crosses(&self, regions: &[Bounds]) -> bool {
+        self.segments.iter().any(|segment| {
+            regions
+                .iter()
+                .any(|bounds| bounds.intersects_segment(&segment.start, &segment.end))
+        })
+    }
+}
+
+/// the render line list of `paths`
+pub fn lines_of_paths(paths: &[RayPath]) -> Vec<(P2, Color)> {
+    let mut lines = Vec::new();
+    for path in paths {
+        path.push_lines(&mut lines);
+    }
+    lines
+}
@@ -0,0 +1,28 @@
+use collision2d::geo::Float;
+
+/// Small seeded pseudo random number generator (SplitMix64).
+/// Used for jittering rays so every pass of the progressive rendering can be reproduced
+#[derive(Debug, Clone)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Rng { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// uniformly distributed in [0, 1)
+    pub fn next_float(&mut self) -> Float {
+        // the upper 53 bits fill the mantissa
+        (self.next_u64() >> 11) as Float / (1_u64 << 53) as Float
+    }
+}
@@ -0,0 +1,152 @@
+use collision2d::geo::*;
+
+use super::Color;
+
+/// shortest visible wavelength in nm
+pub const WAVELENGTH_MIN: Float = 380.;
+/// longest visible wavelength in nm
+pub const WAVELENGTH_MAX: Float = 780.;
+/// sodium d-line in nm, the reference wavelength for refractive indices
+pub const WAVELENGTH_D: Float = 587.6;
+
+/// wavelengths in nm at which the red, green and blue channels of `wavelength_to_rgb` peak
+const CHANNEL_WAVELENGTHS: [Float; 3] = [645., 510., 440.];
+
+/// approximates the linear rgb color of a monochromatic wavelength given in nm
+/// (piecewise linear fit after Dan Bruton)
+pub fn wavelength_to_rgb(wavelength: Float) -> [f32; 3] {
+    let w = wavelength;
+    let (r, g, b) = if w < 380. {
+        (0., 0., 0.)
+    } else if w < 440. {
+        (-(w - 440.) / (440. - 380.), 0., 1.)
+    } else if w < 490. {
+        (0., (w - 440.) / (490. - 440.), 1.)
+    } else if w < 510. {
+        (0., 1., -(w - 510.) / (510. - 490.))
+    } else if w < 580. {
+        ((w - 510.) / (580. - 510.), 1., 0.)
+    } else if w < 645. {
+        (1., -(w - 645.) / (645. - 580.), 0.)
+    } else if w <= 780. {
+        (1., 0., 0.)
+    } else {
+        (0., 0., 0.)
+    };
+    // the eyes sensitivity drops off at the edges of the visible spectrum
+    let falloff = if w < 420. {
+        0.3 + 0.7 * (w - 380.) / (420. - 380.)
+    } else if w > 700. {
+        0.3 + 0.7 * (780. - w) / (780. - 700.)
+    } else {
+        1.
+    };
+    [
+        (r * falloff) as f32,
+        (g * falloff) as f32,
+        (b * falloff) as f32,
+    ]
+}
+
+/// splits `color` into `num_samples` wavelengths spread evenly over the visible spectrum.
+/// Each channel is normalized so that the samples add up to the original color again,
+/// a channel that none of the wavelengths cover goes to the sample closest to its peak.
+/// Alpha is kept as is because it is only used for blending and the cutoff test
+pub fn spectral_samples(color: Color, num_samples: usize) -> Vec<(Float, Color)> {
+    let step = (WAVELENGTH_MAX - WAVELENGTH_MIN) / num_samples as Float;
+    let samples: Vec<(Float, [f32; 3])> = (0..num_samples)
+        .map(|i| {
+            let wavelength = WAVELENGTH_MIN + (i as Float + 0.5) * step;
+            (wavelength, wavelength_to_rgb(wavelength))
+        })
+        .collect();
+    let mut sum = [0_f32; 3];
+    for (_, rgb) in &samples {
+        sum[0] += rgb[0];
+        sum[1] += rgb[1];
+        sum[2] += rgb[2];
+    }
+    // e.g. a single sample in the middle of the spectrum has no blue
+    let closest: Vec<Option<usize>> = CHANNEL_WAVELENGTHS
+        .iter()
+        .map(|peak| {
+            (0..samples.len()).min_by(|a, b| {
+                (samples[*a].0 - peak)
+                    .abs()
+                    .total_cmp(&(samples[*b].0 - peak).abs())
+            })
+        })
+        .collect();
+    samples
+        .into_iter()
+        .enumerate()
+        .map(|(ix, (wavelength, rgb))| {
+            let channel = |ch: usize| {
+                if sum[ch] > 0. {
+                    color[ch] * rgb[ch] / sum[ch]
+                } else if closest[ch] == Some(ix) {
+                    color[ch]
+                } else {
+                    0.
+                }
+            };
+            (wavelength, [channel(0), channel(1), channel(2), color[3]])
+        })
+        .collect()
+}
+
+/// Linear rgb color of a black body at `kelvin`, normalized to a luminance of 1.
+/// Planck's law is integrated against the CIE 1931 color matching functions
+/// (multi lobe gaussian fit after Wyman, Sloan and Shirley) and converted to linear sRGB
+pub fn blackbody_to_rgb(kelvin: Float) -> [f32; 3] {
+    // second radiation constant in m K
+    const C2: Float = 1.4388e-2;
+    let kelvin = kelvin.max(1.);
+    let lobe = |w: Float, mu: Float, sigma_lo: Float, sigma_hi: Float| {
+        let t = (w - mu) / if w < mu { sigma_lo } else { sigma_hi };
+        (-0.5 * t * t).exp()
+    };
+    let (mut x, mut y, mut z) = (0., 0., 0.);
+    let mut w = WAVELENGTH_MIN;
+    while w <= WAVELENGTH_MAX {
+        let meters = w * 1e-9;
+        // the constant factors cancel out in the normalization
+        let radiance = 1. / (meters.powi(5) * ((C2 / (meters * kelvin)).exp() - 1.));
+        x += radiance
+            * (1.056 * lobe(w, 599.8, 37.9, 31.0) + 0.362 * lobe(w, 442.0, 16.0, 26.7)
+                - 0.065 * lobe(w, 501.1, 20.4, 26.2));
+        y += radiance * (0.821 * lobe(w, 568.8, 46.9, 40.5) + 0.286 * lobe(w, 530.9, 16.3, 31.1));
+        z += radiance * (1.217 * lobe(w, 437.0, 11.8, 36.0) + 0.681 * lobe(w, 459.0, 26.0, 13.8));
+        w += 5.;
+    }
+    if y <= 0. {
+        return [0.; 3];
+    }
+    let (x, z) = (x / y, z / y);
+    let y = 1.;
+    [
+        (3.2406 * x - 1.5372 * y - 0.4986 * z).max(0.) as f32,
+        (-0.9689 * x + 1.8758 * y + 0.0415 * z).max(0.) as f32,
+        (0.0557 * x - 0.2040 * y + 1.0570 * z).max(0.) as f32,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spectral_samples_keep_every_channel() {
+        let color = [0.3, 0.5, 0.7, 1.];
+        for num_samples in 1..=8 {
+            let samples = spectral_samples(color, num_samples);
+            for (ch, expected) in color.iter().take(3).enumerate() {
+                let sum: f32 = samples.iter().map(|(_, sample)| sample[ch]).sum();
+                assert!(
+                    (sum - expected).abs() < 1e-5,
+                    "{num_samples} samples, channel {ch}: {sum}"
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use collision2d::geo::*;
+#[cfg(not(target_arch = "wasm32"))]
+use rayon::prelude::*;
+
+/// Settings for the adaptive subdivision of a lights ray fan.
+/// Neighbouring rays whose paths split up get new rays inserted between them
+/// so caustics are filled instead of showing single line bundles
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subdivision {
+    pub enabled: bool,
+    /// maximum number of rays added to a single light per trace
+    pub budget: usize,
+    /// maximum number of times the gap between two original rays is halved
+    pub max_depth: u32,
+    /// paths whose corresponding points are farther apart get subdivided
+    pub max_distance: Float,
+}
+
+impl Default for Subdivision {
+    fn default() -> Self {
+        Subdivision {
+            enabled: false,
+            budget: 10000,
+            max_depth: 6,
+            max_distance: 0.05,
+        }
+    }
+}
+
+/// The objects a ray hits along its dominant path and the points where it hits them,
+/// the last point can be on the canvas bounds
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct PathProbe {
+    pub hits: Vec<usize>,
+    pub points: Vec<P2>,
+}
+
+impl PathProbe {
+    /// whether the paths hit different objects or drift further apart than `max_distance`
+    pub fn diverges(&self, other: &PathProbe, max_distance: Float) -> bool {
+        if self.hits != other.hits || self.points.len() != other.points.len() {
+            return true;
+        }
+        let max_distance_sq = max_distance * max_distance;
+        self.points
+            .iter()
+            .zip(other.points.iter())
+            .any(|(a, b)| distance_squared(a, b) > max_distance_sq)
+    }
+}
+
+impl Subdivision {
+    /// Inserts rays between the neighbours of the ordered `rays` whose probed paths diverge.
+    /// A ray owns half of the gap to each of its neighbours, a new ray in the middle of a gap
+    /// takes half of both sides, so the sum of the `weights` stays the same.
+    /// `closed` connects the last ray to the first one, e.g. for point lights
+    pub fn refine(
+        &self,
+        rays: &[Ray],
+        weights: &[f32],
+        closed: bool,
+        probe: impl Fn(&Ray) -> PathProbe + Sync,
+    ) -> (Vec<Ray>, Vec<f32>) {
+        if !self.enabled || rays.len() < 2 {
+            return (rays.to_vec(), weights.to_vec());
+        }
+        let mut rays = rays.to_vec();
+        // the share of the weight towards the (previous, next) ray
+        let mut shares: Vec<(f32, f32)> = weights.iter().map(|w| (w * 0.5, w * 0.5)).collect();
+        let mut probes = probe_all(&rays, &probe);
+        let mut gaps: Vec<(usize, usize)> = (1..rays.len()).map(|ix| (ix - 1, ix)).collect();
+        if closed {
+            gaps.push((rays.len() - 1, 0));
+        } else {
+            // the outermost rays have no neighbour to share with
+            let last = shares.len() - 1;
+            shares[0] = (0., weights[0]);
+            shares[last] = (weights[last], 0.);
+        }
+
+        let mut added = 0;
+        for _ in 0..self.max_depth {
+            let split: Vec<(usize, usize)> = gaps
+                .iter()
+                .filter(|(a, b)| probes[*a].diverges(&probes[*b], self.max_distance))
+                .take(self.budget - added)
+                .copied()
+                .collect();
+            if split.is_empty() {
+                break;
+            }
+            let middles: Vec<Ray> = split
+                .iter()
+                .map(|(a, b)| middle_ray(&rays[*a], &rays[*b]))
+                .collect();
+            probes.extend(probe_all(&middles, &probe));
+            gaps.clear();
+            for ((a, b), middle) in split.into_iter().zip(middles) {
+                let m = rays.len();
+                let from_a = shares[a].1 * 0.5;
+                let from_b = shares[b].0 * 0.5;
+                shares[a].1 -= from_a;
+                shares[b].0 -= from_b;
+                shares.push((from_a, from_b));
+                rays.push(middle);
+                gaps.push((a, m));
+                gaps.push((m, b));
+            }
+            added = rays.len() - weights.len();
+            if added >= self.budget {
+                break;
+            }
+        }
+        let weights = shares.iter().map(|(prev, next)| prev + next).collect();
+        (rays, weights)
+    }
+}
+
+fn probe_all(rays: &[Ray], probe: &(impl Fn(&Ray) -> PathProbe + Sync)) -> Vec<PathProbe> {
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        rays.par_iter().map(probe).collect()
+    }
+    #[cfg(target_arch = "wasm32")]
+    {
+        rays.iter().map(probe).collect()
+    }
+}
+
+/// the ray halfway between `a` and `b` in origin and direction
+fn middle_ray(a: &Ray, b: &Ray) -> Ray {
+    let origin = a.get_origin() + (b.get_origin() - a.get_origin()) * 0.5;
+    let direction = a.get_direction().into_inner() + b.get_direction().into_inner();
+    let direction = if direction.norm() < EPSILON {
+        // opposite directions, turn a by 90 degrees towards b
+        let a_dir = a.get_direction().into_inner();
+        V2::new(-a_dir.y, a_dir.x)
+    } else {
+        direction.normalize()
+    };
+    Ray::from_origin(origin, direction)
+}
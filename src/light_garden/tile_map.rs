@@ -2,6 +2,16 @@ use crate::light_garden::*;
 use std::fmt::{Display, Formatter, Result};
 use std::slice::Iter;
 
+/// How the tile map finds the objects a ray can hit
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum TileTraversal {
+    /// the objects in the slab of the tile the ray starts in
+    #[default]
+    Slabs,
+    /// walks the ray through the tiles it crosses and tests the objects overlapping them
+    Grid,
+}
+
 // tiles go from the top left in rows to the bottom right
 #[derive(Clone, PartialEq, Debug)]
 pub struct TileMap {
@@ -12,6 +22,7 @@ pub struct TileMap {
     num_slabs: usize,
     pub tiles: Vec<Tile>,
     pub tile_map_enabled: bool,
+    pub traversal: TileTraversal,
 }
 
 impl TileMap {
@@ -48,6 +59,7 @@ impl TileMap {
                     aabb,
                     slabs,
                     range_map: Vec::new(),
+                    overlap_indices: Vec::new(),
                 });
             }
         }
@@ -59,6 +71,7 @@ impl TileMap {
             num_slabs,
             tiles,
             tile_map_enabled: true,
+            traversal: TileTraversal::default(),
         }
     }
 
@@ -114,8 +127,8 @@ impl TileMap {
     }
 
     pub fn update_object(&mut self, ix: usize, object: &mut Object) {
+        // `moved` is cleared by the tracer once the bvh is refitted as well
         if object.moved && self.tile_map_enabled {
-            object.moved = false;
             for tile in self.tiles.iter_mut() {
                 tile.update_overlap(ix, object);
             }
@@ -131,6 +144,58 @@ impl TileMap {
         }
     }
 
+    /// Walks the tiles `ray` crosses in order with a DDA.
+    /// `visit` gets the objects overlapping each tile and the distance along the ray
+    /// at which it leaves the tile, the walk stops once `visit` returns true
+    pub fn walk(&self, ray: &Ray, mut visit: impl FnMut(&[usize], Float) -> bool) {
+        let half = V2::new(self.window_width * 0.5, self.window_height * 0.5);
+        let bounds = Bounds {
+            min: P2::origin() - half,
+            max: P2::origin() + half,
+        };
+        let Some(entry) = bounds.entry_distance(ray) else {
+            return;
+        };
+        let direction = ray.get_direction().into_inner();
+        let origin = ray.get_origin() + half;
+        let step = V2::new(
+            self.window_width / self.num_tilesx as Float,
+            self.window_height / self.num_tilesy as Float,
+        );
+        let start = origin + direction * entry;
+        let mut ix = [
+            ((start.x / step.x) as i64).clamp(0, self.num_tilesx as i64 - 1),
+            ((start.y / step.y) as i64).clamp(0, self.num_tilesy as i64 - 1),
+        ];
+        let num_tiles = [self.num_tilesx as i64, self.num_tilesy as i64];
+        let mut step_ix = [0; 2];
+        // distance along the ray to the next tile border per axis
+        let mut t_max = [Float::MAX; 2];
+        let mut t_delta = [Float::MAX; 2];
+        for axis in 0..2 {
+            let d = direction[axis];
+            if d.abs() < EPSILON {
+                continue;
+            }
+            step_ix[axis] = if d > 0. { 1 } else { -1 };
+            let border = (if d > 0. { ix[axis] + 1 } else { ix[axis] }) as Float * step[axis];
+            t_max[axis] = (border - origin[axis]) / d;
+            t_delta[axis] = step[axis] / d.abs();
+        }
+        loop {
+            let axis = if t_max[0] < t_max[1] { 0 } else { 1 };
+            let tile = &self.tiles[(ix[0] + ix[1] * num_tiles[0]) as usize];
+            if visit(&tile.overlap_indices, t_max[axis]) {
+                return;
+            }
+            ix[axis] += step_ix[axis];
+            t_max[axis] += t_delta[axis];
+            if step_ix[axis] == 0 || ix[axis] < 0 || ix[axis] >= num_tiles[axis] {
+                return;
+            }
+        }
+    }
+
     pub fn get_tile(&self, pos: &P2) -> Option<&Tile> {
         let mut pos = *pos;
         pos += V2::new(self.window_width * 0.5, self.window_height * 0.5);
@@ -198,6 +263,8 @@ pub struct Tile {
     pub aabb: Aabb,
     pub slabs: Vec<Slab>,
     pub range_map: Vec<Option<SlabRange>>,
+    /// the objects whose aabb overlaps the tile, used by `TileMap::walk`
+    overlap_indices: Vec<usize>,
 }
 
 impl Tile {
@@ -206,6 +273,7 @@ impl Tile {
             aabb,
             slabs: slabs.into(),
             range_map: Vec::new(),
+            overlap_indices: Vec::new(),
         }
     }
 
@@ -235,7 +303,7 @@ impl Tile {
     }
 
     pub fn update_overlap(&mut self, obj_index: usize, obj: &Object) -> bool {
-        let obj_aabb = obj.get_aabb();
+        let obj_aabb = obj.cached_aabb();
         let res = obj_aabb.intersect(&self.aabb).is_some()
             || obj_aabb.contains(&self.aabb.get_origin())
             || self.aabb.contains(&obj_aabb.get_origin());
@@ -243,8 +311,12 @@ impl Tile {
             // the object overlaps self.aabb
             self.remove_object_from_slabs(obj_index, true);
             self.range_map[obj_index] = None;
+            if !self.overlap_indices.contains(&obj_index) {
+                self.overlap_indices.push(obj_index);
+            }
         } else {
             // object is outside of self.aabb
+            self.overlap_indices.retain(|&ix| ix != obj_index);
             self.update_object(obj_index, obj);
         }
         res
@@ -254,7 +326,7 @@ impl Tile {
     /// into the tiles range map.
     /// this function should only be called by update_overlap
     fn update_object(&mut self, obj_index: usize, obj: &Object) {
-        self.range_map[obj_index] = self.get_range(&obj.get_aabb());
+        self.range_map[obj_index] = self.get_range(obj.cached_aabb());
         if let Some(srange) = self.range_map[obj_index] {
             for ix in srange.into_iter() {
                 self.slabs[ix].insert_object(obj_index);
@@ -268,11 +340,18 @@ impl Tile {
         }
         if !keep_indices {
             self.range_map.remove(obj_index);
+            self.overlap_indices.retain(|&ix| ix != obj_index);
+            for ix in self.overlap_indices.iter_mut() {
+                if *ix > obj_index {
+                    *ix -= 1;
+                }
+            }
         }
     }
 
     pub fn clear(&mut self) {
         self.range_map.clear();
+        self.overlap_indices.clear();
         for slab in self.slabs.iter_mut() {
             slab.clear();
         }
@@ -373,10 +452,10 @@ impl Slab {
     }
 
     fn overlaps(&self, obj: &Object) -> bool {
-        let geo = obj.get_geometry();
+        let geo = obj.cached_geometry();
         between_rays(&obj.get_origin(), &self.rleft, &self.rright)
-            || self.rleft.intersect(&geo).is_some()
-            || self.rright.intersect(&geo).is_some()
+            || self.rleft.intersect(geo).is_some()
+            || self.rright.intersect(geo).is_some()
             || geo.intersect(&self.ls).is_some()
     }
 
@@ -0,0 +1,276 @@
+use crate::light_garden::*;
+
+/// the light the paths were traced for and the path of every ray
+#[derive(Debug, Clone, PartialEq)]
+struct LightCache {
+    light: Light,
+    paths: Vec<RayPath>,
+    /// indices of rays whose paths are missing or out of date because of the frame budget
+    pending: Vec<usize>,
+}
+
+/// the paths of the rays of an emissive object, the object itself is compared by `TraceCache::update`
+#[derive(Debug, Clone, PartialEq)]
+struct EmitterCache {
+    paths: Vec<RayPath>,
+    /// indices of rays whose paths are missing or out of date because of the frame budget
+    pending: Vec<usize>,
+}
+
+/// Keeps the traced paths of the lights and emissive objects between frames.
+/// Only the rays whose paths cross the bounds of a changed, added or removed object are traced
+/// again, all rays of a light or emissive object are traced again if the source itself changed
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct TraceCache {
+    lights: Vec<Option<LightCache>>,
+    /// indexed like the objects, None for objects without emission
+    emitters: Vec<Option<EmitterCache>>,
+    /// the objects the cached paths were traced with
+    objects: Vec<Object>,
+    /// hash of the tracer settings the cached paths were traced with
+    settings: u64,
+}
+
+impl TraceCache {
+    pub fn clear(&mut self) {
+        self.lights.clear();
+        self.emitters.clear();
+        self.objects.clear();
+    }
+
+    /// Compares `objects` to the ones of the last trace and returns the regions
+    /// in which cached paths are out of date. The hit objects and media of the cached paths
+    /// are moved to the new object indices. Everything is dropped if `settings` changed
+    pub fn update(&mut self, objects: &[Object], settings: u64) -> Vec<Bounds> {
+        if settings != self.settings {
+            self.settings = settings;
+            self.clear();
+        }
+        let old = &self.objects;
+        // the changed objects are the ones between a common prefix and a common suffix
+        let prefix = old
+            .iter()
+            .zip(objects)
+            .take_while(|(a, b)| same_object(a, b))
+            .count();
+        let max_suffix = old.len().min(objects.len()) - prefix;
+        let suffix = old
+            .iter()
+            .rev()
+            .zip(objects.iter().rev())
+            .take(max_suffix)
+            .take_while(|(a, b)| same_object(a, b))
+            .count();
+        if prefix == old.len() && prefix == objects.len() {
+            return Vec::new();
+        }
+        let regions: Vec<Bounds> = old[prefix..old.len() - suffix]
+            .iter()
+            .chain(&objects[prefix..objects.len() - suffix])
+            .map(|obj| Bounds::from_aabb(obj.cached_aabb()))
+            .collect();
+        // the changed emissive objects are traced again
+        let old_end = old.len() - suffix;
+        self.emitters.resize(old.len(), None);
+        self.emitters
+            .splice(prefix..old_end, vec![None; objects.len() - suffix - prefix]);
+        // objects behind the changed ones shifted
+        let shift = objects.len() as i64 - old.len() as i64;
+        if shift != 0 {
+            for path in self
+                .lights
+                .iter_mut()
+                .flatten()
+                .flat_map(|cache| cache.paths.iter_mut())
+                .chain(
+                    self.emitters
+                        .iter_mut()
+                        .flatten()
+                        .flat_map(|cache| cache.paths.iter_mut()),
+                )
+            {
+                if let PathSource::Object(ix) = &mut path.source
+                    && *ix >= old_end
+                {
+                    *ix = (*ix as i64 + shift) as usize;
+                }
+                for segment in path.segments.iter_mut() {
+                    for ix in segment.hit.iter_mut().chain(segment.medium.iter_mut()) {
+                        if *ix >= old_end {
+                            *ix = (*ix as i64 + shift) as usize;
+                        }
+                    }
+                }
+            }
+        }
+        self.objects = objects.to_vec();
+        regions
+    }
+
+    /// The cached paths and the pending ray indices of the light at `light_ix` if they were
+    /// traced for `light` with the same number of rays. Takes them out of the cache
+    pub fn take_paths(
+        &mut self,
+        light_ix: usize,
+        light: &Light,
+        num_rays: usize,
+    ) -> Option<(Vec<RayPath>, Vec<usize>)> {
+        let cache = self.lights.get_mut(light_ix)?.take()?;
+        (cache.light == *light && cache.paths.len() == num_rays)
+            .then_some((cache.paths, cache.pending))
+    }
+
+    pub fn store_paths(
+        &mut self,
+        light_ix: usize,
+        light: &Light,
+        paths: Vec<RayPath>,
+        pending: Vec<usize>,
+    ) {
+        if self.lights.len() <= light_ix {
+            self.lights.resize(light_ix + 1, None);
+        }
+        self.lights[light_ix] = Some(LightCache {
+            light: light.clone(),
+            paths,
+            pending,
+        });
+    }
+
+    /// Marks the cached rays of the light at `light_ix` that cross `regions` as pending,
+    /// or drops the light if its origin lies in one of them.
+    /// For lights that were not traced after `update` returned the regions
+    pub fn mark_stale(&mut self, light_ix: usize, regions: &[Bounds]) {
+        let Some(slot) = self.lights.get_mut(light_ix) else {
+            return;
+        };
+        let Some(cache) = slot else {
+            return;
+        };
+        if regions
+            .iter()
+            .any(|bounds| bounds.contains(&cache.light.get_origin()))
+        {
+            *slot = None;
+            return;
+        }
+        cache.pending = stale_rays(&cache.paths, &cache.pending, regions);
+    }
+
+    /// The cached paths and the pending ray indices of the emissive object at `obj_ix`
+    /// if they were traced with the same number of rays. Takes them out of the cache
+    pub fn take_emitted_paths(
+        &mut self,
+        obj_ix: usize,
+        num_rays: usize,
+    ) -> Option<(Vec<RayPath>, Vec<usize>)> {
+        let cache = self.emitters.get_mut(obj_ix)?.take()?;
+        (cache.paths.len() == num_rays).then_some((cache.paths, cache.pending))
+    }
+
+    pub fn store_emitted_paths(&mut self, obj_ix: usize, paths: Vec<RayPath>, pending: Vec<usize>) {
+        if self.emitters.len() <= obj_ix {
+            self.emitters.resize(obj_ix + 1, None);
+        }
+        self.emitters[obj_ix] = Some(EmitterCache { paths, pending });
+    }
+
+    /// Marks the cached rays of the emissive object at `obj_ix` that cross `regions` as pending.
+    /// For emissive objects that were not traced after `update` returned the regions
+    pub fn mark_emitter_stale(&mut self, obj_ix: usize, regions: &[Bounds]) {
+        if let Some(Some(cache)) = self.emitters.get_mut(obj_ix) {
+            cache.pending = stale_rays(&cache.paths, &cache.pending, regions);
+        }
+    }
+
+    /// number of rays of all lights and emissive objects that still have to be traced
+    pub fn pending_rays(&self) -> usize {
+        self.lights
+            .iter()
+            .flatten()
+            .map(|cache| cache.pending.len())
+            .chain(
+                self.emitters
+                    .iter()
+                    .flatten()
+                    .map(|cache| cache.pending.len()),
+            )
+            .sum()
+    }
+
+    /// drops the caches of lights that do not exist anymore
+    pub fn truncate_lights(&mut self, num_lights: usize) {
+        self.lights.truncate(num_lights);
+    }
+}
+
+/// The indices of the rays that are `pending` or whose `paths` cross `regions`, in ray order
+pub fn stale_rays(paths: &[RayPath], pending: &[usize], regions: &[Bounds]) -> Vec<usize> {
+    let mut stale = vec![false; paths.len()];
+    for ix in pending {
+        stale[*ix] = true;
+    }
+    (0..paths.len())
+        .filter(|ix| stale[*ix] || paths[*ix].crosses(regions))
+        .collect()
+}
+
+/// Splits `indices` into at most `max` evenly spread indices to trace now and the rest,
+/// repeated calls on the rest interleave until all are traced
+pub fn decimate(indices: Vec<usize>, max: usize) -> (Vec<usize>, Vec<usize>) {
+    if indices.len() <= max {
+        return (indices, Vec::new());
+    }
+    if max == 0 {
+        return (Vec::new(), indices);
+    }
+    let stride = indices.len().div_ceil(max);
+    let (now, later): (Vec<_>, Vec<_>) = indices
+        .into_iter()
+        .enumerate()
+        .partition(|(i, _)| i % stride == 0);
+    (
+        now.into_iter().map(|(_, ix)| ix).collect(),
+        later.into_iter().map(|(_, ix)| ix).collect(),
+    )
+}
+
+/// equal for tracing, the bins of detectors and `Object::moved` are not compared
+pub fn same_object(a: &Object, b: &Object) -> bool {
+    let same_enum = match (&a.object_enum, &b.object_enum) {
+        (ObjectE::Detector(a), ObjectE::Detector(b)) => {
+            a.line_segment == b.line_segment && a.pass_through == b.pass_through
+        }
+        (a, b) => a == b,
+    };
+    same_enum && a.material_opt == b.material_opt && a.emission == b.emission
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimate_without_budget() {
+        assert_eq!(decimate(vec![0, 1, 2], 0), (vec![], vec![0, 1, 2]));
+        assert_eq!(decimate(vec![], 0), (vec![], vec![]));
+    }
+
+    #[test]
+    fn decimate_exact_fit() {
+        assert_eq!(decimate(vec![0, 1, 2], 3), (vec![0, 1, 2], vec![]));
+    }
+
+    #[test]
+    fn decimate_interleaves() {
+        let (now, later) = decimate((0..10).collect(), 4);
+        assert_eq!(now, vec![0, 3, 6, 9]);
+        assert_eq!(later, vec![1, 2, 4, 5, 7, 8]);
+        let (now, later) = decimate(later, 4);
+        assert_eq!(now, vec![1, 4, 7]);
+        assert_eq!(later, vec![2, 5, 8]);
+        let (now, later) = decimate(later, 4);
+        assert_eq!(now, vec![2, 5, 8]);
+        assert!(later.is_empty());
+    }
+}
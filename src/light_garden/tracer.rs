@@ -1,5 +1,15 @@
 use crate::light_garden::*;
-use std::{mem, slice::Iter};
+use std::{
+    hash::{DefaultHasher, Hash, Hasher},
+    mem,
+    slice::Iter,
+};
+
+/// distance by which rays that continue through a surface are moved away from it
+pub const SURFACE_OFFSET: Float = 1e-7;
+/// rays traced per frame even if they exceed the frame budget,
+/// also the fewest rays the time per ray is measured with
+const MIN_BUDGET_RAYS: usize = 1000;
 
 pub struct Tracer {
     objects: Vec<Object>,
@@ -9,10 +19,49 @@ pub struct Tracer {
     pub max_bounce: u32,
     pub chunk_size: usize,
     pub cutoff_color: Color,
+    /// number of wavelengths each light ray is split into, 0 disables dispersion
+    pub spectral_samples: usize,
+    /// trace a jittered batch of rays every frame and accumulate them in the render texture
+    pub progressive: bool,
+    pub progressive_seed: u64,
+    /// tracing pauses once this many passes are accumulated
+    pub max_passes: u32,
+    pub subdivision: Subdivision,
+    pub render_mode: RenderMode,
+    /// the rays of the last `trace_density`
+    pub density: DensityBuffer,
+    /// the paths of every light and emissive object ray of the last trace
+    ray_paths: Vec<RayPath>,
+    accumulated_passes: u32,
+    /// whether the last call to `trace_all` traced a new pass
+    pass_traced: bool,
+    /// hash of everything that changes the traced image, see `Tracer::fingerprint`
+    scene_fingerprint: u64,
+    /// increases with every edit of the objects, the lights and the items being drawn
+    scene_generation: u64,
     pub grid: Grid,
     pub canvas_bounds: Rect,
     pub trace_time_vd: VecDeque<f64>,
     tile_map: TileMap,
+    bvh: Bvh,
+    /// use the bvh to find the nearest object instead of the tile map
+    pub bvh_enabled: bool,
+    /// reuse the paths of rays that are not affected by changed objects
+    pub incremental: bool,
+    trace_cache: TraceCache,
+    /// number of light and emitted rays traced by the last `trace_all`, the others came from the cache
+    retraced_rays: usize,
+    /// Trace at most as many rays per frame as fit into `frame_budget`,
+    /// the rest is filled in over the following frames. Needs incremental re-tracing
+    pub frame_budget_enabled: bool,
+    /// milliseconds
+    pub frame_budget: f64,
+    /// measured from `trace_time_vd` and the number of traced rays
+    ms_per_ray: Option<f64>,
+    /// rays left out by the frame budget
+    pending_rays: usize,
+    /// set when the job of the background worker is outdated
+    cancel: CancelFlag,
     pub debug_key_pressed: bool,
 }
 
@@ -29,6 +78,7 @@ impl Tracer {
             tile_map.push_obj(obj);
         }
         println!("canvas_bounds: {canvas_bounds:?}");
+        let bvh = Bvh::new(&objects);
         Tracer {
             lights: vec![light],
             objects,
@@ -36,34 +86,67 @@ impl Tracer {
             drawing_light: None,
             max_bounce: 5,
             cutoff_color: [0.001; 4],
+            spectral_samples: 0,
+            progressive: false,
+            progressive_seed: 0,
+            max_passes: 256,
+            subdivision: Subdivision::default(),
+            render_mode: RenderMode::default(),
+            density: DensityBuffer::new(0, 0),
+            ray_paths: Vec::new(),
+            accumulated_passes: 0,
+            pass_traced: false,
+            scene_fingerprint: 0,
+            scene_generation: 0,
             chunk_size: 100,
             grid: Grid::new(canvas_bounds),
             canvas_bounds: *canvas_bounds,
             trace_time_vd: VecDeque::new(),
             tile_map,
+            bvh,
+            bvh_enabled: true,
+            incremental: true,
+            trace_cache: TraceCache::default(),
+            retraced_rays: 0,
+            frame_budget_enabled: false,
+            frame_budget: 16.,
+            ms_per_ray: None,
+            pending_rays: 0,
+            cancel: CancelFlag::default(),
             debug_key_pressed: false,
         }
     }
 
+    /// marks the scene as changed for `fingerprint`
+    fn scene_changed(&mut self) {
+        self.scene_generation = self.scene_generation.wrapping_add(1);
+    }
+
     pub fn clear_objects(&mut self) {
+        self.scene_changed();
         self.drawing_object = None;
         self.objects.clear();
         self.tile_map.clear_tiles();
+        self.bvh = Bvh::default();
     }
 
     pub fn clear(&mut self) {
+        self.scene_changed();
         self.drawing_object = None;
         self.drawing_light = None;
         self.objects.clear();
         self.lights.clear();
         self.tile_map.clear_tiles();
+        self.bvh = Bvh::default();
     }
 
     pub fn add_drawing_object(&mut self, obj: Object) {
+        self.scene_changed();
         self.drawing_object = Some(obj);
     }
 
     pub fn finish_drawing_object(&mut self, abort: bool) {
+        self.scene_changed();
         if abort {
             self.drawing_object = None
         } else if let Some(obj) = self.drawing_object.take() {
@@ -73,10 +156,12 @@ impl Tracer {
     }
 
     pub fn add_drawing_light(&mut self, light: Light) {
+        self.scene_changed();
         self.drawing_light = Some(light);
     }
 
     pub fn finish_drawing_light(&mut self, abort: bool) {
+        self.scene_changed();
         if abort {
             self.drawing_light = None
         } else {
@@ -85,33 +170,53 @@ impl Tracer {
     }
 
     pub fn push_object(&mut self, object: Object) {
+        self.scene_changed();
         self.tile_map.push_obj(&object);
         self.objects.push(object);
     }
 
     pub fn push_light(&mut self, light: Light) {
+        self.scene_changed();
         self.lights.push(light);
     }
 
+    pub fn get_object(&self, ix: usize) -> &Object {
+        &self.objects[ix]
+    }
+
+    pub fn get_light(&self, ix: usize) -> &Light {
+        &self.lights[ix]
+    }
+
+    /// for edits, use `get_object` to only read
     pub fn index_object(&mut self, ix: usize) -> &mut Object {
+        self.scene_changed();
         &mut self.objects[ix]
     }
 
+    /// for edits, use `get_light` to only read
     pub fn index_light(&mut self, ix: usize) -> &mut Light {
+        self.scene_changed();
         &mut self.lights[ix]
     }
 
     pub fn replace_object(&mut self, ix: usize, mut object: Object) {
+        self.scene_changed();
+        object.invalidate_geometry();
         self.tile_map.update_object(ix, &mut object);
         self.objects[ix] = object;
     }
 
     pub fn remove_object(&mut self, ix: usize) {
+        self.scene_changed();
         self.objects.remove(ix);
         self.tile_map.remove_object(ix);
+        // the indices behind `ix` shifted
+        self.bvh = Bvh::default();
     }
 
     pub fn remove_light(&mut self, ix: usize) {
+        self.scene_changed();
         self.lights.remove(ix);
     }
 
@@ -123,9 +228,12 @@ impl Tracer {
         self.lights.iter()
     }
 
+    /// the tile map and the bvh are updated before the next trace
     pub fn obj_changed(&mut self, obj_index: usize) {
-        self.tile_map
-            .update_object(obj_index, &mut self.objects[obj_index]);
+        self.scene_changed();
+        let obj = &mut self.objects[obj_index];
+        obj.moved = true;
+        obj.invalidate_geometry();
     }
 
     pub fn update_tile_map(&mut self) {
@@ -134,6 +242,24 @@ impl Tracer {
         }
     }
 
+    /// Refits the bvh to the moved objects, rebuilds it if objects were added or removed,
+    /// and updates the tile map. Clears `Object::moved` afterwards
+    pub fn update_acceleration(&mut self) {
+        // objects can be edited through `index_object` without `obj_changed`
+        for obj in self.objects.iter_mut().filter(|obj| obj.moved) {
+            obj.invalidate_geometry();
+        }
+        if self.bvh.num_objects() != self.objects.len() {
+            self.bvh.rebuild(&self.objects);
+        } else {
+            self.bvh.refit(&self.objects);
+        }
+        self.update_tile_map();
+        for obj in self.objects.iter_mut() {
+            obj.moved = false;
+        }
+    }
+
     pub fn enable_tile_map(&mut self, enable: bool) {
         if enable {
             self.tile_map.clear_tiles();
@@ -153,6 +279,8 @@ impl Tracer {
             num_tiles_y,
             num_slabs,
         );
+        tile_map.traversal = self.tile_map.traversal;
+        tile_map.tile_map_enabled = self.tile_map.tile_map_enabled;
         for obj in self.objects.iter() {
             tile_map.push_obj(obj);
         }
@@ -163,18 +291,35 @@ impl Tracer {
         self.tile_map.tile_map_enabled
     }
 
+    /// (tiles along x, tiles along y, slabs per tile) of the tile map
+    fn tile_counts(&self) -> (usize, usize, usize) {
+        (
+            self.tile_map.get_num_tiles_x(),
+            self.tile_map.get_num_tiles_y(),
+            self.tile_map.get_num_slabs(),
+        )
+    }
+
     pub fn get_tile_map(&self) -> &TileMap {
         &self.tile_map
     }
 
+    pub fn set_tile_traversal(&mut self, traversal: TileTraversal) {
+        self.tile_map.traversal = traversal;
+    }
+
     pub fn get_tile(&self, pos: &P2) -> Option<&Tile> {
         self.tile_map.get_tile(pos)
     }
 
     pub fn resize(&mut self, bounds: &Rect) {
+        self.restart_accumulation();
+        self.trace_cache.clear();
         self.canvas_bounds = *bounds;
         self.grid.update_canvas_bounds(bounds);
+        let traversal = self.tile_map.traversal;
         self.tile_map = TileMap::new(bounds.width, bounds.height, 10, 10, 8);
+        self.tile_map.traversal = traversal;
         for obj in self.objects.iter() {
             self.tile_map.push_obj(obj);
         }
@@ -200,6 +345,230 @@ impl Tracer {
         // recreate light rays
         for light in self.lights.iter_mut() {
             light.set_num_rays(None);
+            light.migrate_power();
+        }
+    }
+
+    pub fn accumulated_passes(&self) -> u32 {
+        self.accumulated_passes
+    }
+
+    pub fn restart_accumulation(&mut self) {
+        self.accumulated_passes = 0;
+    }
+
+    /// the render texture has to be cleared before drawing the lines of the last `trace_all`
+    pub fn render_texture_cleared(&self) -> bool {
+        !self.progressive || (self.pass_traced && self.accumulated_passes == 1)
+    }
+
+    /// Hashes the edit count of the scene and the settings that change the traced image.
+    /// Tracing itself, like filling the detector bins, does not count as a change
+    pub fn fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (
+            self.scene_generation,
+            self.max_bounce,
+            self.spectral_samples,
+            self.progressive_seed,
+        )
+            .hash(&mut hasher);
+        self.cutoff_color.map(f32::to_bits).hash(&mut hasher);
+        (
+            self.canvas_bounds.width.to_bits(),
+            self.canvas_bounds.height.to_bits(),
+        )
+            .hash(&mut hasher);
+        (
+            self.subdivision.enabled,
+            self.subdivision.budget,
+            self.subdivision.max_depth,
+            self.subdivision.max_distance.to_bits(),
+        )
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    pub fn retraced_rays(&self) -> usize {
+        self.retraced_rays
+    }
+
+    /// The structured paths of the last traced pass, the render lines are derived from them.
+    /// Holds the paths of the background worker once its output is applied
+    pub fn ray_paths(&self) -> &[RayPath] {
+        &self.ray_paths
+    }
+
+    /// Where the energy of every light and emissive object went in the last trace.
+    /// For every path: emitted = escaped + cutoff + max_bounce + absorbed,
+    /// and at dielectric hits the reflected and transmitted energy add up to the incident
+    pub fn energy_report(&self) -> EnergyReport {
+        EnergyReport::from_paths(&self.ray_paths, self.objects.len())
+    }
+
+    pub fn pending_rays(&self) -> usize {
+        self.pending_rays
+    }
+
+    /// number of light and emitted rays that fit into the frame budget, unlimited until a time is measured
+    fn ray_budget(&self) -> usize {
+        match self.ms_per_ray {
+            Some(ms_per_ray) if self.frame_budget_enabled => {
+                ((self.frame_budget / ms_per_ray) as usize).max(MIN_BUDGET_RAYS)
+            }
+            _ => usize::MAX,
+        }
+    }
+
+    /// hash of the settings the cached paths depend on
+    fn cache_settings(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (self.max_bounce, self.spectral_samples).hash(&mut hasher);
+        self.cutoff_color.map(f32::to_bits).hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// `fingerprint` including the settings that only change how the image is traced
+    pub fn job_fingerprint(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        (
+            self.fingerprint(),
+            self.chunk_size,
+            self.bvh_enabled,
+            self.incremental,
+            self.frame_budget_enabled,
+            self.frame_budget.to_bits(),
+            self.tile_map_enabled(),
+            self.tile_map.traversal,
+            self.tile_counts(),
+        )
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// copies the scene and the settings for a trace on the background worker
+    pub fn trace_job(&self, generation: u64) -> TraceJob {
+        TraceJob {
+            generation,
+            objects: self.objects.clone(),
+            lights: self.lights.clone(),
+            drawing_object: self.drawing_object.clone(),
+            drawing_light: self.drawing_light.clone(),
+            max_bounce: self.max_bounce,
+            chunk_size: self.chunk_size,
+            cutoff_color: self.cutoff_color,
+            spectral_samples: self.spectral_samples,
+            subdivision: self.subdivision.clone(),
+            bvh_enabled: self.bvh_enabled,
+            incremental: self.incremental,
+            frame_budget_enabled: self.frame_budget_enabled,
+            frame_budget: self.frame_budget,
+            tile_map_enabled: self.tile_map_enabled(),
+            traversal: self.tile_map.traversal,
+            tile_counts: self.tile_counts(),
+            canvas_bounds: self.canvas_bounds,
+        }
+    }
+
+    /// Takes over the scene and the settings of `job`. Only objects that differ from the
+    /// current ones count as moved, so the bvh, the tile map and the trace cache are kept
+    pub fn apply_job(&mut self, job: TraceJob) {
+        let num_objects = self.objects.len();
+        let mut objects = job.objects;
+        for (ix, obj) in objects.iter_mut().enumerate() {
+            obj.moved = self
+                .objects
+                .get(ix)
+                .is_none_or(|old| !same_object(old, obj));
+        }
+        self.scene_changed();
+        self.objects = objects;
+        self.lights = job.lights;
+        self.drawing_object = job.drawing_object;
+        self.drawing_light = job.drawing_light;
+        self.max_bounce = job.max_bounce;
+        self.chunk_size = job.chunk_size;
+        self.cutoff_color = job.cutoff_color;
+        self.spectral_samples = job.spectral_samples;
+        self.subdivision = job.subdivision;
+        self.bvh_enabled = job.bvh_enabled;
+        self.incremental = job.incremental;
+        self.frame_budget_enabled = job.frame_budget_enabled;
+        self.frame_budget = job.frame_budget;
+        if job.canvas_bounds.width != self.canvas_bounds.width
+            || job.canvas_bounds.height != self.canvas_bounds.height
+        {
+            self.resize(&job.canvas_bounds);
+        } else if num_objects != self.objects.len() {
+            // the tile map indices shifted
+            self.tile_map.clear_tiles();
+            for obj in &self.objects {
+                self.tile_map.push_obj(obj);
+            }
+        }
+        self.canvas_bounds = job.canvas_bounds;
+        if job.tile_counts != self.tile_counts() {
+            let (num_tiles_x, num_tiles_y, num_slabs) = job.tile_counts;
+            self.new_tile_map(num_tiles_x, num_tiles_y, num_slabs);
+        }
+        if job.tile_map_enabled != self.tile_map_enabled() {
+            self.enable_tile_map(job.tile_map_enabled);
+        }
+        self.tile_map.traversal = job.traversal;
+    }
+
+    /// Traces the lights and emissive objects without helper lines for the background worker.
+    /// None if `cancel` was set before the trace finished
+    pub fn trace_output(&mut self, generation: u64, cancel: CancelFlag) -> Option<TraceOutput> {
+        self.cancel = cancel;
+        self.trace_rays();
+        if self.cancel.is_cancelled() {
+            return None;
+        }
+        let detector_bins = self
+            .objects
+            .iter()
+            .enumerate()
+            .filter_map(|(ix, obj)| match &obj.object_enum {
+                ObjectE::Detector(detector) => Some((ix, detector.bins.clone())),
+                _ => None,
+            })
+            .collect();
+        Some(TraceOutput {
+            generation,
+            paths: mem::take(&mut self.ray_paths),
+            detector_bins,
+            trace_time: self.trace_time_vd.back().copied().unwrap_or_default(),
+            retraced_rays: self.retraced_rays,
+            pending_rays: self.pending_rays,
+        })
+    }
+
+    /// takes over the paths, detector bins and statistics of a finished background trace
+    /// and returns its lines
+    pub fn apply_output(&mut self, output: TraceOutput) -> Vec<(P2, Color)> {
+        for (ix, bins) in output.detector_bins {
+            // the scene may have changed since the job was submitted
+            if let Some(Object {
+                object_enum: ObjectE::Detector(detector),
+                ..
+            }) = self.objects.get_mut(ix)
+                && detector.num_bins.max(1) == bins.len()
+            {
+                detector.bins = bins;
+            }
+        }
+        self.retraced_rays = output.retraced_rays;
+        self.pending_rays = output.pending_rays;
+        self.push_trace_time(output.trace_time);
+        self.ray_paths = output.paths;
+        lines_of_paths(&self.ray_paths)
+    }
+
+    fn push_trace_time(&mut self, milliseconds: f64) {
+        self.trace_time_vd.push_back(milliseconds);
+        if self.trace_time_vd.len() > 20 {
+            self.trace_time_vd.pop_front();
         }
     }
 
@@ -218,7 +587,7 @@ impl Tracer {
                     .map(|ray| {
                         let mut line_strip = vec![ray.get_origin()];
                         self.trace_reflective(&mut line_strip, ray, self.max_bounce);
-                        (line_strip, light.get_color())
+                        (line_strip, light.get_ray_color())
                     })
                     .collect::<Vec<(Vec<P2>, Color)>>();
                 all_line_strips.extend(line_strips);
@@ -231,7 +600,7 @@ impl Tracer {
                     .map(|ray| {
                         let mut line_strip = vec![ray.get_origin()];
                         self.trace_reflective(&mut line_strip, ray, self.max_bounce);
-                        (line_strip, light.get_color())
+                        (line_strip, light.get_ray_color())
                     })
                     .collect::<Vec<(Vec<P2>, Color)>>();
                 all_line_strips.extend(line_strips);
@@ -250,7 +619,7 @@ impl Tracer {
             ret_intersect = Some(intersection_point.get_first().0);
         }
         for obj in self.objects.iter().chain(self.drawing_object.iter()) {
-            if let Some(reflected) = ray.reflect_on(&obj.get_geometry()) {
+            if let Some(reflected) = ray.reflect_on(obj.cached_geometry()) {
                 if let Some(intersect) = ret_intersect {
                     if distance(&ray.get_origin(), &reflected.get_origin())
                         < distance(&ray.get_origin(), &intersect)
@@ -274,60 +643,251 @@ impl Tracer {
     }
 
     pub fn trace_all(&mut self) -> Vec<(P2, Color)> {
+        let mut all_lines = self.trace_rays();
+        // helper lines would add up in the accumulated image
+        if !self.progressive {
+            all_lines.extend(self.helper_lines());
+        }
+        all_lines
+    }
+
+    /// Traces all lights into `density` which has to be sized first.
+    /// Progressive passes add up until the accumulation restarts.
+    /// Needs no gpu, so the image can be rendered headless:
+    /// `tracer.density.resize(w, h); tracer.trace_density(); tracer.density.to_rgb8(1.)`
+    pub fn trace_density(&mut self) {
+        let lines = self.trace_rays();
+        if self.render_texture_cleared() {
+            self.density.clear();
+        }
+        self.density.splat_lines(&lines);
+    }
+
+    /// control lines of curved mirrors and the grid
+    pub fn helper_lines(&self) -> Vec<(P2, Color)> {
+        let mut lines = Vec::new();
+        // draw control lines for cubic bezier curves
+        for obj in self.objects.iter().chain(self.drawing_object.iter()) {
+            if let ObjectE::CurvedMirror(cm) = obj.object_enum {
+                lines.append(&mut cm.get_control_lines());
+            }
+        }
+
+        // draw grid
+        lines.extend(self.grid.get_render_lines());
+        lines
+    }
+
+    /// the light rays of all lights and emissive objects as pairs of points
+    fn trace_rays(&mut self) -> Vec<(P2, Color)> {
+        self.update_acceleration();
+        let mut rng = None;
+        self.pass_traced = false;
+        if self.progressive {
+            let fingerprint = self.fingerprint();
+            if fingerprint != self.scene_fingerprint {
+                self.scene_fingerprint = fingerprint;
+                self.restart_accumulation();
+            }
+            if self.accumulated_passes >= self.max_passes {
+                return Vec::new();
+            }
+            self.accumulated_passes += 1;
+            rng = Some(Rng::new(
+                self.progressive_seed
+                    .wrapping_add(self.accumulated_passes as u64),
+            ));
+        }
+        self.pass_traced = true;
         let instant_start = Instant::now();
-        let mut all_lines: Vec<(P2, Color)> = Vec::new();
-        for light in self.lights.iter().chain(self.drawing_light.iter()) {
-            let mut refractive_index = 1.;
-            for obj in self.objects.iter().chain(self.drawing_object.iter()) {
-                if obj.contains(&light.get_origin()) {
-                    if let Some(material) = obj.material_opt {
-                        refractive_index = material.refractive_index;
+        let mut all_paths: Vec<RayPath> = Vec::new();
+        // jittered and subdivided rays change every frame
+        let use_cache = self.incremental && !self.progressive && !self.subdivision.enabled;
+        let mut cache = mem::take(&mut self.trace_cache);
+        let regions = if use_cache {
+            cache.update(&self.objects, self.cache_settings())
+        } else {
+            cache.clear();
+            Vec::new()
+        };
+        cache.truncate_lights(self.lights.len());
+        self.retraced_rays = 0;
+        let mut ray_budget = self.ray_budget();
+        // index of the first light a cancelled trace did not finish
+        let mut cancelled_at = None;
+        for (light_ix, light) in self
+            .lights
+            .iter()
+            .chain(self.drawing_light.iter())
+            .enumerate()
+        {
+            if self.cancel.is_cancelled() {
+                cancelled_at = Some(light_ix);
+                break;
+            }
+            let start_media = MediaStack::at_point(&self.objects, &light.get_origin());
+            let samples = self.color_samples(light.get_ray_color());
+            let jittered;
+            let rays: &[Ray] = match rng.as_mut() {
+                Some(rng) => {
+                    jittered = light.jittered_rays(rng);
+                    &jittered
+                }
+                None => light.get_rays(),
+            };
+            // angular profile
+            let weights = light.ray_weights(rays);
+            let (rays, weights) =
+                self.subdivision
+                    .refine(rays, &weights, light.rays_wrap_around(), |ray| {
+                        self.probe(ray, start_media.clone())
+                    });
+            let source = PathSource::Light(light_ix);
+            // the light that is being drawn changes every frame
+            if use_cache && light_ix < self.lights.len() {
+                let cached = cache.take_paths(light_ix, light, rays.len());
+                let retrace: Vec<usize> = match &cached {
+                    // the medium at the origin could have changed
+                    Some((paths, pending))
+                        if !regions
+                            .iter()
+                            .any(|bounds| bounds.contains(&light.get_origin())) =>
+                    {
+                        stale_rays(paths, pending, &regions)
                     }
+                    _ => (0..rays.len()).collect(),
+                };
+                let (retrace, pending) = decimate(retrace, ray_budget);
+                ray_budget = ray_budget.saturating_sub(retrace.len());
+                let traced =
+                    self.trace_paths(source, &retrace, &rays, &weights, &samples, &start_media);
+                if self.cancel.is_cancelled() {
+                    // the traced paths are incomplete, the old ones are kept as pending
+                    if let Some((paths, _)) = cached {
+                        let mut stale = retrace;
+                        stale.extend(pending);
+                        cache.store_paths(light_ix, light, paths, stale);
+                    }
+                    cancelled_at = Some(light_ix + 1);
+                    break;
+                }
+                self.retraced_rays += retrace.len();
+                let mut paths = cached.map(|(paths, _)| paths).unwrap_or_else(|| {
+                    (0..rays.len()).map(|ix| RayPath::new(source, ix)).collect()
+                });
+                for (ix, path) in retrace.into_iter().zip(traced) {
+                    paths[ix] = path;
                 }
+                for ix in &pending {
+                    paths[*ix].pending = true;
+                }
+                all_paths.extend_from_slice(&paths);
+                cache.store_paths(light_ix, light, paths, pending);
+                continue;
             }
-            #[cfg(not(target_arch = "wasm32"))]
-            {
-                let lines: Vec<(P2, Color)> = light
-                    .get_rays()
-                    .par_chunks(self.chunk_size)
-                    .map(|rays| {
-                        let mut lines = Vec::new();
-                        for ray in rays {
-                            self.trace(
-                                &mut lines,
-                                ray,
-                                light.get_color(),
-                                refractive_index,
-                                self.max_bounce,
-                            );
-                        }
-                        lines
-                    })
-                    .collect::<Vec<Vec<(P2, Color)>>>()
-                    .concat();
-                all_lines.extend(lines);
-            };
-            #[cfg(target_arch = "wasm32")]
-            {
-                let lines: Vec<(P2, Color)> = light
-                    .get_rays()
-                    .iter()
-                    .map(|ray| {
-                        let mut lines = Vec::new();
-                        self.trace(
-                            &mut lines,
-                            ray,
-                            light.get_color(),
-                            refractive_index,
-                            self.max_bounce,
-                        );
-                        lines
-                    })
-                    .collect::<Vec<Vec<(P2, Color)>>>()
-                    .concat();
-                all_lines.extend(lines);
+            self.retraced_rays += rays.len();
+            let indices: Vec<usize> = (0..rays.len()).collect();
+            all_paths.extend(self.trace_paths(
+                source,
+                &indices,
+                &rays,
+                &weights,
+                &samples,
+                &start_media,
+            ));
+        }
+
+        // the lights a cancelled trace did not get to are traced by the next job
+        if let Some(first) = cancelled_at {
+            for light_ix in first..self.lights.len() {
+                cache.mark_stale(light_ix, &regions);
+            }
+        }
+
+        // emissive objects, every ray has its own color and starting medium
+        let mut cancelled_emitter = None;
+        for (obj_ix, obj) in self
+            .objects
+            .iter()
+            .chain(self.drawing_object.iter())
+            .enumerate()
+        {
+            let Some(emission) = &obj.emission else {
+                continue;
             };
+            if self.cancel.is_cancelled() {
+                cancelled_emitter = Some(obj_ix);
+                break;
+            }
+            let jitter = rng.as_mut().map_or(0.5, |rng| rng.next_float());
+            let rays = emission.rays(obj, jitter);
+            let source = PathSource::Object(obj_ix);
+            // the object that is being drawn changes every frame
+            if use_cache && obj_ix < self.objects.len() {
+                let cached = cache.take_emitted_paths(obj_ix, rays.len());
+                let retrace: Vec<usize> = match &cached {
+                    Some((paths, pending)) => stale_rays(paths, pending, &regions),
+                    None => (0..rays.len()).collect(),
+                };
+                // emissive objects share the budget with the lights
+                let (retrace, pending) = decimate(retrace, ray_budget);
+                ray_budget = ray_budget.saturating_sub(retrace.len());
+                let traced = self.trace_emitted_paths(source, &retrace, &rays);
+                if self.cancel.is_cancelled() {
+                    // the traced paths are incomplete, the old ones are kept as pending
+                    if let Some((paths, _)) = cached {
+                        let mut stale = retrace;
+                        stale.extend(pending);
+                        cache.store_emitted_paths(obj_ix, paths, stale);
+                    }
+                    cancelled_emitter = Some(obj_ix + 1);
+                    break;
+                }
+                self.retraced_rays += retrace.len();
+                let mut paths = cached.map(|(paths, _)| paths).unwrap_or_else(|| {
+                    (0..rays.len()).map(|ix| RayPath::new(source, ix)).collect()
+                });
+                for (ix, path) in retrace.into_iter().zip(traced) {
+                    paths[ix] = path;
+                }
+                for ix in &pending {
+                    paths[*ix].pending = true;
+                }
+                all_paths.extend_from_slice(&paths);
+                cache.store_emitted_paths(obj_ix, paths, pending);
+                continue;
+            }
+            self.retraced_rays += rays.len();
+            let indices: Vec<usize> = (0..rays.len()).collect();
+            all_paths.extend(self.trace_emitted_paths(source, &indices, &rays));
         }
+        if let Some(first) = cancelled_emitter {
+            for obj_ix in first..self.objects.len() {
+                cache.mark_emitter_stale(obj_ix, &regions);
+            }
+        }
+        self.pending_rays = cache.pending_rays();
+        self.trace_cache = cache;
+
+        // refill the detector bins, progressive passes add up until the accumulation restarts
+        if !self.progressive || self.accumulated_passes == 1 {
+            for obj in self.objects.iter_mut() {
+                if let ObjectE::Detector(detector) = &mut obj.object_enum {
+                    detector.clear_bins();
+                }
+            }
+        }
+        let detector_hits: Vec<(usize, P2, Color)> = all_paths
+            .iter()
+            .flat_map(|path| path.detector_hits(&self.objects))
+            .collect();
+        for (ix, p, color) in detector_hits {
+            if let ObjectE::Detector(detector) = &mut self.objects[ix].object_enum {
+                detector.record(&p, &color);
+            }
+        }
+        let all_lines = lines_of_paths(&all_paths);
+        self.ray_paths = all_paths;
 
         // fill limit testing
         // all_lines.resize(
@@ -338,157 +898,539 @@ impl Tracer {
         // ),
         // );
 
-        // draw control lines for cubic bezier curves
-        for obj in self.objects.iter().chain(self.drawing_object.iter()) {
-            if let ObjectE::CurvedMirror(cm) = obj.object_enum {
-                all_lines.append(&mut cm.get_control_lines());
-            }
-        }
-
-        // draw grid
-        all_lines.extend(self.grid.get_render_lines());
-
-        self.trace_time_vd
-            .push_back(instant_start.elapsed().as_micros() as f64 / 1000.0);
-        if self.trace_time_vd.len() > 20 {
-            self.trace_time_vd.pop_front();
+        let trace_time = instant_start.elapsed().as_micros() as f64 / 1000.0;
+        self.push_trace_time(trace_time);
+        // few rays are dominated by the fixed costs
+        if self.retraced_rays >= MIN_BUDGET_RAYS {
+            self.ms_per_ray = Some(trace_time / self.retraced_rays as f64);
         }
         self.debug_key_pressed = false;
         all_lines
     }
 
+    /// splits `color` into its spectral samples as (wavelength, color),
+    /// a single sample without wavelength if dispersion is disabled
+    fn color_samples(&self, color: Color) -> Vec<(Option<Float>, Color)> {
+        if self.spectral_samples > 0 {
+            spectral_samples(color, self.spectral_samples)
+                .into_iter()
+                .map(|(wavelength, color)| (Some(wavelength), color))
+                .collect()
+        } else {
+            vec![(None, color)]
+        }
+    }
+
+    /// Traces a single ray, the segments of all of its branches go to `path`.
+    /// The energy that leaves the canvas or is dropped by the cutoff or `max_bounce`
+    /// is added to the totals of `path`
     pub fn trace(
         &self,
-        rays: &mut Vec<(P2, Color)>,
+        path: &mut RayPath,
         ray: &Ray,
         color: Color,
-        refractive_index: Float,
+        wavelength: Option<Float>,
+        media: MediaStack,
         max_bounce: u32,
     ) {
-        let mut trace_rays = vec![(*ray, color, refractive_index)];
+        path.emitted += color_energy(&color);
+        // a spectral sample carries about 1 / spectral_samples of the ray color
+        let cutoff = match wavelength {
+            Some(_) => scale_color(&self.cutoff_color, 1. / self.spectral_samples.max(1) as f32),
+            None => self.cutoff_color,
+        };
+        // (ray, color, objects the ray is inside of)
+        let mut trace_rays = vec![(*ray, color, media)];
         let mut back_buffer = Vec::new();
         if self.debug_key_pressed && ray.get_direction().y == -1.0 {
             println!("debug");
         }
-        for _ in 0..max_bounce {
+        for depth in 0..max_bounce {
             if trace_rays.is_empty() {
                 return;
             }
-            for (ray, color, refractive_index) in &trace_rays {
-                if (color[0] < self.cutoff_color[0]
-                    && color[1] < self.cutoff_color[1]
-                    && color[2] < self.cutoff_color[2])
-                    || color[3] < self.cutoff_color[3]
-                {
+            for (ray, color, media) in &trace_rays {
+                if below_cutoff(color, &cutoff) {
+                    path.cutoff += color_energy(color);
                     continue;
                 }
-                let overlaps = self
-                    .tile_map
-                    .get_tile(&ray.get_origin())
-                    .map(|tile| tile.get_overlaps())
-                    .unwrap_or_default();
-
-                // find the nearest object
-                let mut nearest: Float = f64::MAX;
-                // (intersection point, normal, object index)
-                let mut nearest_target: Option<(P2, Normal, usize)> = None;
-                if self.tile_map.tile_map_enabled {
-                    if let Some(slab) = self.tile_map.index(ray) {
-                        for index in slab.object_index_iterator().chain(overlaps.iter()) {
-                            if let Some(intersections) =
-                                ray.intersect(&self.objects[*index].get_geometry())
-                            {
-                                for (intersection, normal) in intersections {
-                                    let dist_sq =
-                                        distance_squared(&ray.get_origin(), &intersection);
-                                    if dist_sq < nearest {
-                                        nearest = dist_sq;
-                                        nearest_target = Some((intersection, normal, *index));
-                                    }
-                                }
+                if let Some((intersection, normal, index)) = self.nearest_hit(ray) {
+                    let medium = media.current(&self.objects);
+                    // absorption along the way to the intersection
+                    let hit_color = medium.map_or(*color, |(_, m)| {
+                        m.attenuate(*color, distance(&ray.get_origin(), &intersection))
+                    });
+                    // energy leaving the hit on the side of the ray and on the other side
+                    let mut reflected_energy = 0.;
+                    let mut transmitted_energy = 0.;
+                    'interaction: {
+                        if let ObjectE::Detector(detector) = &self.objects[index].object_enum {
+                            if detector.pass_through {
+                                let direction = ray.get_direction().into_inner();
+                                back_buffer.push((
+                                    Ray::from_origin(
+                                        intersection + direction * SURFACE_OFFSET,
+                                        direction,
+                                    ),
+                                    hit_color,
+                                    media.clone(),
+                                ));
+                                transmitted_energy += color_energy(&hit_color);
                             }
+                            break 'interaction;
                         }
-                    }
-                } else {
-                    for (index, obj) in self.objects.iter().enumerate() {
-                        if let Some(intersections) = ray.intersect(&obj.get_geometry()) {
-                            for (intersection, normal) in intersections {
-                                let dist_sq = distance_squared(&ray.get_origin(), &intersection);
-                                if dist_sq < nearest {
-                                    nearest = dist_sq;
-                                    nearest_target = Some((intersection, normal, index));
-                                }
-                            }
-                        }
-                    }
-                }
+                        match self.objects[index].get_material() {
+                            Some(material) => match material.kind.surface_shares() {
+                                None => {
+                                    // crossing the boundary means entering or leaving the object
+                                    let refracted_media = media.toggled(index);
+                                    let refracted_medium = refracted_media.current(&self.objects);
+                                    if refracted_medium.map(|(ix, _)| ix)
+                                        == medium.map(|(ix, _)| ix)
+                                    {
+                                        // the boundary lies inside of a medium with a higher
+                                        // priority so the ray passes through unchanged
+                                        let direction = ray.get_direction().into_inner();
+                                        back_buffer.push((
+                                            Ray::from_origin(
+                                                intersection + direction * SURFACE_OFFSET,
+                                                direction,
+                                            ),
+                                            hit_color,
+                                            refracted_media,
+                                        ));
+                                        transmitted_energy += color_energy(&hit_color);
+                                        break 'interaction;
+                                    }
+
+                                    let refractive_index = |m: Option<(usize, Material)>| {
+                                        m.map_or(1., |(_, m)| m.refractive_index_at(wavelength))
+                                    };
+                                    let (reflected, orefracted, reflectance) = ray.refract(
+                                        &intersection,
+                                        &normal,
+                                        refractive_index(medium),
+                                        refractive_index(refracted_medium),
+                                    );
 
-                if let Some((intersection, normal, index)) = nearest_target {
-                    let obj = &self.objects[index];
-                    if let Some(material) = obj.material_opt {
-                        // get the refracted rays refractive_index
-                        let mut refracted_refractive_index = 1.; // air
-                        if obj.contains(&ray.get_origin()) {
-                            for (ix, o) in self.objects.iter().enumerate() {
-                                if ix != index && o.contains(&intersection) {
-                                    if let Some(material) = o.get_material() {
-                                        refracted_refractive_index = material.refractive_index;
-                                        break;
+                                    // total internal reflection keeps all of the energy
+                                    let refl = if orefracted.is_some() {
+                                        reflectance as f32
+                                    } else {
+                                        1.
+                                    };
+                                    let omrefl = 1. - refl;
+                                    // points back to the side the ray came from
+                                    let facing = reflected.get_direction().into_inner()
+                                        - ray.get_direction().into_inner();
+                                    for scattered in Tracer::scatter(
+                                        &material,
+                                        &reflected,
+                                        &facing,
+                                        scale_color(&hit_color, refl),
+                                    ) {
+                                        reflected_energy += color_energy(&scattered.1);
+                                        back_buffer.push((scattered.0, scattered.1, media.clone()));
+                                    }
+                                    if let Some(refracted) = orefracted {
+                                        for scattered in Tracer::scatter(
+                                            &material,
+                                            &refracted,
+                                            &-facing,
+                                            scale_color(&hit_color, omrefl),
+                                        ) {
+                                            transmitted_energy += color_energy(&scattered.1);
+                                            back_buffer.push((
+                                                scattered.0,
+                                                scattered.1,
+                                                refracted_media.clone(),
+                                            ));
+                                        }
                                     }
                                 }
+                                Some((reflect, mut transmit)) => {
+                                    // filters are slabs that apply their pass band on entry
+                                    let is_filter =
+                                        matches!(material.kind, MaterialKind::Filter { .. });
+                                    if is_filter && media.contains(index) {
+                                        transmit = [1.; 3];
+                                    }
+                                    let transmitted_media = if is_filter {
+                                        media.toggled(index)
+                                    } else {
+                                        media.clone()
+                                    };
+                                    let reflected = ray.reflect(&intersection, &normal);
+                                    let facing = reflected.get_direction().into_inner()
+                                        - ray.get_direction().into_inner();
+                                    if reflect.iter().any(|share| *share > 0.) {
+                                        for scattered in Tracer::scatter(
+                                            &material,
+                                            &reflected,
+                                            &facing,
+                                            mul_color(&hit_color, &reflect),
+                                        ) {
+                                            reflected_energy += color_energy(&scattered.1);
+                                            back_buffer.push((
+                                                scattered.0,
+                                                scattered.1,
+                                                media.clone(),
+                                            ));
+                                        }
+                                    }
+                                    if transmit.iter().any(|share| *share > 0.) {
+                                        // thin surfaces do not bend the transmitted ray
+                                        let direction = ray.get_direction().into_inner();
+                                        let transmitted = Ray::from_origin(
+                                            intersection + direction * SURFACE_OFFSET,
+                                            direction,
+                                        );
+                                        for scattered in Tracer::scatter(
+                                            &material,
+                                            &transmitted,
+                                            &-facing,
+                                            mul_color(&hit_color, &transmit),
+                                        ) {
+                                            transmitted_energy += color_energy(&scattered.1);
+                                            back_buffer.push((
+                                                scattered.0,
+                                                scattered.1,
+                                                transmitted_media.clone(),
+                                            ));
+                                        }
+                                    }
+                                }
+                            },
+                            None => {
+                                back_buffer.push((
+                                    ray.reflect(&intersection, &normal),
+                                    hit_color,
+                                    media.clone(),
+                                ));
+                                reflected_energy += color_energy(&hit_color);
                             }
-                        } else {
-                            refracted_refractive_index = material.refractive_index;
-                        }
-
-                        let result = ray.refract(
-                            &intersection,
-                            &normal,
-                            *refractive_index,
-                            refracted_refractive_index,
-                        );
-                        let (reflected, orefracted, reflectance) = result;
-                        rays.push((ray.get_origin(), *color));
-                        rays.push((reflected.get_origin(), *color));
-
-                        let refl = reflectance as f32;
-                        let omrefl = 1. - refl;
-                        let reflected_color =
-                            [color[0] * refl, color[1] * refl, color[2] * refl, color[3]];
-                        back_buffer.push((reflected, reflected_color, *refractive_index));
-                        // self.trace(rays, &reflected, color1, refractive_index, max_bounce);
-                        if let Some(refracted) = orefracted {
-                            let refracted_color = [
-                                color[0] * omrefl,
-                                color[1] * omrefl,
-                                color[2] * omrefl,
-                                color[3],
-                            ];
-                            back_buffer.push((
-                                refracted,
-                                refracted_color,
-                                refracted_refractive_index,
-                            ));
                         }
-                    } else {
-                        rays.push((ray.get_origin(), *color));
-                        rays.push((intersection, *color));
-                        back_buffer.push((
-                            ray.reflect(&intersection, &normal),
-                            *color,
-                            *refractive_index,
-                        ));
                     }
+                    // the mirrored direction differs by 2 cos(incidence) along the normal
+                    let mirrored = ray
+                        .reflect(&intersection, &normal)
+                        .get_direction()
+                        .into_inner();
+                    let cos_incidence =
+                        ((ray.get_direction().into_inner() - mirrored).norm() * 0.5).min(1.);
+                    path.segments.push(PathSegment {
+                        start: ray.get_origin(),
+                        end: intersection,
+                        start_color: *color,
+                        end_color: hit_color,
+                        hit: Some(index),
+                        normal: Some(normal),
+                        incidence_angle: Some(cos_incidence.acos()),
+                        medium: medium.map(|(ix, _)| ix),
+                        depth,
+                        wavelength,
+                        reflected_energy,
+                        transmitted_energy,
+                    });
                 } else {
                     // handle canvas bounds
                     if let Some(canvas_intersect) = ray.intersect(&self.canvas_bounds) {
-                        rays.push((ray.get_origin(), *color));
-                        rays.push((canvas_intersect.get_first().0, *color));
+                        let end = canvas_intersect.get_first().0;
+                        let medium = media.current(&self.objects);
+                        let end_color = medium.map_or(*color, |(_, m)| {
+                            m.attenuate(*color, distance(&ray.get_origin(), &end))
+                        });
+                        path.escaped += color_energy(&end_color);
+                        path.segments.push(PathSegment {
+                            start: ray.get_origin(),
+                            end,
+                            start_color: *color,
+                            end_color,
+                            hit: None,
+                            normal: None,
+                            incidence_angle: None,
+                            medium: medium.map(|(ix, _)| ix),
+                            depth,
+                            wavelength,
+                            reflected_energy: 0.,
+                            transmitted_energy: 0.,
+                        });
+                    } else {
+                        // starts outside of the canvas
+                        path.escaped += color_energy(color);
                     }
                 }
             }
             trace_rays.clear();
             mem::swap(&mut trace_rays, &mut back_buffer);
         }
+        // the rays that are left would have been traced further
+        for (_, color, _) in &trace_rays {
+            if below_cutoff(color, &cutoff) {
+                path.cutoff += color_energy(color);
+            } else {
+                path.max_bounce += color_energy(color);
+            }
+        }
     }
+
+    /// traces the rays at `indices` with all of their spectral samples, one path per index
+    fn trace_paths(
+        &self,
+        source: PathSource,
+        indices: &[usize],
+        rays: &[Ray],
+        weights: &[f32],
+        samples: &[(Option<Float>, Color)],
+        media: &MediaStack,
+    ) -> Vec<RayPath> {
+        let trace_path = |ix: &usize| {
+            let mut path = RayPath::new(source, *ix);
+            if self.cancel.is_cancelled() {
+                return path;
+            }
+            for (wavelength, color) in samples {
+                self.trace(
+                    &mut path,
+                    &rays[*ix],
+                    scale_color(color, weights[*ix]),
+                    *wavelength,
+                    media.clone(),
+                    self.max_bounce,
+                );
+            }
+            path
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            indices
+                .par_iter()
+                .with_min_len(self.chunk_size)
+                .map(trace_path)
+                .collect()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            indices.iter().map(trace_path).collect()
+        }
+    }
+
+    /// traces the `emitted` rays of an emissive object at `indices` as (ray, color),
+    /// one path per index that starts in the medium at the origin of the ray
+    fn trace_emitted_paths(
+        &self,
+        source: PathSource,
+        indices: &[usize],
+        emitted: &[(Ray, Color)],
+    ) -> Vec<RayPath> {
+        let trace_path = |ix: &usize| {
+            let mut path = RayPath::new(source, *ix);
+            if self.cancel.is_cancelled() {
+                return path;
+            }
+            let (ray, color) = &emitted[*ix];
+            let media = MediaStack::at_point(&self.objects, &ray.get_origin());
+            for (wavelength, color) in self.color_samples(*color) {
+                self.trace(
+                    &mut path,
+                    ray,
+                    color,
+                    wavelength,
+                    media.clone(),
+                    self.max_bounce,
+                );
+            }
+            path
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            indices
+                .par_iter()
+                .with_min_len(self.chunk_size)
+                .map(trace_path)
+                .collect()
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            indices.iter().map(trace_path).collect()
+        }
+    }
+
+    /// the nearest intersection of `ray` with an object as (intersection point, normal, object index)
+    fn nearest_hit(&self, ray: &Ray) -> Option<(P2, Normal, usize)> {
+        let mut nearest_target: Option<(P2, Normal, usize)> = None;
+        // squared distance to the nearest intersection so far
+        let mut nearest: Float = f64::MAX;
+        // tests an object and returns the squared distance to the nearest hit so far
+        let mut test = |index: usize| {
+            if let Some(intersections) = ray.intersect(self.objects[index].cached_geometry()) {
+                for (intersection, normal) in intersections {
+                    let dist_sq = distance_squared(&ray.get_origin(), &intersection);
+                    if dist_sq < nearest {
+                        nearest = dist_sq;
+                        nearest_target = Some((intersection, normal, index));
+                    }
+                }
+            }
+            nearest
+        };
+        if self.bvh_enabled {
+            self.bvh.traverse(ray, |index| Some(test(index).sqrt()));
+        } else if self.tile_map.tile_map_enabled {
+            match self.tile_map.traversal {
+                TileTraversal::Slabs => {
+                    let overlaps = self
+                        .tile_map
+                        .get_tile(&ray.get_origin())
+                        .map(|tile| tile.get_overlaps())
+                        .unwrap_or_default();
+                    if let Some(slab) = self.tile_map.index(ray) {
+                        for index in slab.object_index_iterator().chain(overlaps.iter()) {
+                            test(*index);
+                        }
+                    }
+                }
+                TileTraversal::Grid => {
+                    // objects can overlap several tiles
+                    let mut tested: Vec<usize> = Vec::new();
+                    let mut nearest_sq = Float::MAX;
+                    self.tile_map.walk(ray, |indices, exit| {
+                        for index in indices {
+                            if !tested.contains(index) {
+                                tested.push(*index);
+                                nearest_sq = test(*index);
+                            }
+                        }
+                        // a hit inside of the tile can not be beaten by the tiles behind it
+                        nearest_sq <= exit * exit
+                    });
+                }
+            }
+        } else {
+            for index in 0..self.objects.len() {
+                test(index);
+            }
+        }
+        nearest_target
+    }
+
+    /// Follows only the brighter branch at every surface, rough materials are treated as smooth.
+    /// Cheap enough to compare the paths of neighbouring rays for the adaptive subdivision
+    fn probe(&self, ray: &Ray, mut media: MediaStack) -> PathProbe {
+        let mut probe = PathProbe::default();
+        let mut ray = *ray;
+        for _ in 0..self.max_bounce {
+            let Some((intersection, normal, index)) = self.nearest_hit(&ray) else {
+                if let Some(canvas_intersect) = ray.intersect(&self.canvas_bounds) {
+                    probe.points.push(canvas_intersect.get_first().0);
+                }
+                break;
+            };
+            probe.hits.push(index);
+            probe.points.push(intersection);
+            let direction = ray.get_direction().into_inner();
+            let straight = Ray::from_origin(intersection + direction * SURFACE_OFFSET, direction);
+            let object = &self.objects[index];
+            ray = match (&object.object_enum, object.material_opt) {
+                (ObjectE::Detector(detector), _) => {
+                    if !detector.pass_through {
+                        break;
+                    }
+                    straight
+                }
+                (_, None) => ray.reflect(&intersection, &normal),
+                (_, Some(material)) => match material.kind.surface_shares() {
+                    Some((reflect, transmit)) => {
+                        let reflect = reflect.iter().sum::<f32>();
+                        let transmit = transmit.iter().sum::<f32>();
+                        if reflect == 0. && transmit == 0. {
+                            // absorbed
+                            break;
+                        }
+                        if reflect >= transmit {
+                            ray.reflect(&intersection, &normal)
+                        } else {
+                            straight
+                        }
+                    }
+                    None => {
+                        let medium = media.current(&self.objects);
+                        let refracted_media = media.toggled(index);
+                        let refracted_medium = refracted_media.current(&self.objects);
+                        if refracted_medium.map(|(ix, _)| ix) == medium.map(|(ix, _)| ix) {
+                            media = refracted_media;
+                            straight
+                        } else {
+                            let refractive_index = |m: Option<(usize, Material)>| {
+                                m.map_or(1., |(_, m)| m.refractive_index_at(None))
+                            };
+                            let (reflected, orefracted, reflectance) = ray.refract(
+                                &intersection,
+                                &normal,
+                                refractive_index(medium),
+                                refractive_index(refracted_medium),
+                            );
+                            match orefracted {
+                                Some(refracted) if reflectance < 0.5 => {
+                                    media = refracted_media;
+                                    refracted
+                                }
+                                _ => reflected,
+                            }
+                        }
+                    }
+                },
+            };
+        }
+        probe
+    }
+
+    /// replaces a specular ray by the fan of rays a rough material scatters it into.
+    /// `facing` points to the side of the surface the ray leaves to
+    fn scatter(
+        material: &Material,
+        specular: &Ray,
+        facing: &V2,
+        color: Color,
+    ) -> Vec<(Ray, Color)> {
+        if !material.is_rough() || facing.norm() < EPSILON {
+            return vec![(*specular, color)];
+        }
+        let origin = specular.get_origin();
+        material
+            .scatter(&specular.get_direction().into_inner(), &facing.normalize())
+            .into_iter()
+            .map(|(direction, share)| {
+                (
+                    Ray::from_origin(origin + direction * SURFACE_OFFSET, direction),
+                    scale_color(&color, share),
+                )
+            })
+            .collect()
+    }
+}
+
+/// rays darker than `cutoff` are not traced any further
+fn below_cutoff(color: &Color, cutoff: &Color) -> bool {
+    (color[0] < cutoff[0] && color[1] < cutoff[1] && color[2] < cutoff[2]) || color[3] < cutoff[3]
+}
+
+/// scales the rgb channels of `color`, alpha is kept
+fn scale_color(color: &Color, factor: f32) -> Color {
+    [
+        color[0] * factor,
+        color[1] * factor,
+        color[2] * factor,
+        color[3],
+    ]
+}
+
+/// multiplies the rgb channels of `color` by `factors`, alpha is kept
+fn mul_color(color: &Color, factors: &[f32; 3]) -> Color {
+    [
+        color[0] * factors[0],
+        color[1] * factors[1],
+        color[2] * factors[2],
+        color[3],
+    ]
 }
@@ -0,0 +1,155 @@
+use crate::light_garden::*;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender, TryRecvError},
+    },
+    thread,
+};
+
+/// The scene and the settings the background worker needs for a trace, see `Tracer::trace_job`
+#[derive(Debug, Clone)]
+pub struct TraceJob {
+    /// increases with every submitted job
+    pub generation: u64,
+    pub objects: Vec<Object>,
+    pub lights: Vec<Light>,
+    pub drawing_object: Option<Object>,
+    pub drawing_light: Option<Light>,
+    pub max_bounce: u32,
+    pub chunk_size: usize,
+    pub cutoff_color: Color,
+    pub spectral_samples: usize,
+    pub subdivision: Subdivision,
+    pub bvh_enabled: bool,
+    pub incremental: bool,
+    pub frame_budget_enabled: bool,
+    pub frame_budget: f64,
+    pub tile_map_enabled: bool,
+    pub traversal: TileTraversal,
+    /// (tiles along x, tiles along y, slabs per tile) of the tile map
+    pub tile_counts: (usize, usize, usize),
+    pub canvas_bounds: Rect,
+}
+
+/// the result of a finished `TraceJob`
+#[derive(Debug, Clone)]
+pub struct TraceOutput {
+    pub generation: u64,
+    pub paths: Vec<RayPath>,
+    /// (object index, bins) of every detector
+    pub detector_bins: Vec<(usize, Vec<DetectorBin>)>,
+    /// milliseconds
+    pub trace_time: f64,
+    pub retraced_rays: usize,
+    /// rays left out by the frame budget, the worker keeps tracing until none are left
+    pub pending_rays: usize,
+}
+
+/// Set once a newer job is submitted, the tracer checks it between rays
+#[derive(Debug, Clone, Default)]
+pub struct CancelFlag(Arc<AtomicBool>);
+
+impl CancelFlag {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// Traces on a separate thread so a heavy scene does not block the ui.
+/// The thread keeps its own `Tracer`, so the acceleration structures and the trace cache
+/// survive between jobs. Only the newest job is traced, older ones are cancelled
+pub struct TraceWorker {
+    jobs: Sender<(TraceJob, CancelFlag)>,
+    outputs: Receiver<TraceOutput>,
+    /// flag of the newest submitted job
+    cancel: CancelFlag,
+    submitted: u64,
+    finished: u64,
+}
+
+impl TraceWorker {
+    pub fn new(canvas_bounds: &Rect) -> Self {
+        let (job_sender, job_receiver) = mpsc::channel::<(TraceJob, CancelFlag)>();
+        let (output_sender, output_receiver) = mpsc::channel();
+        let canvas_bounds = *canvas_bounds;
+        thread::spawn(move || {
+            let mut tracer = Tracer::new(&canvas_bounds);
+            let mut generation = 0;
+            let mut cancel = CancelFlag::default();
+            loop {
+                // rays left out by the frame budget are traced until a new job arrives
+                let next = if tracer.pending_rays() > 0 {
+                    match job_receiver.try_recv() {
+                        Ok(next) => Some(next),
+                        Err(TryRecvError::Empty) => None,
+                        Err(TryRecvError::Disconnected) => break,
+                    }
+                } else {
+                    match job_receiver.recv() {
+                        Ok(next) => Some(next),
+                        Err(_) => break,
+                    }
+                };
+                if let Some(mut next) = next {
+                    // jobs that queued up meanwhile are outdated
+                    while let Ok(newer) = job_receiver.try_recv() {
+                        next = newer;
+                    }
+                    let (job, job_cancel) = next;
+                    cancel = job_cancel;
+                    generation = job.generation;
+                    tracer.apply_job(job);
+                }
+                if let Some(output) = tracer.trace_output(generation, cancel.clone())
+                    && output_sender.send(output).is_err()
+                {
+                    // the worker was dropped
+                    break;
+                }
+            }
+        });
+        TraceWorker {
+            jobs: job_sender,
+            outputs: output_receiver,
+            cancel: CancelFlag::default(),
+            submitted: 0,
+            finished: 0,
+        }
+    }
+
+    /// cancels the job in flight and queues `job`
+    pub fn submit(&mut self, job: TraceJob) {
+        self.cancel.cancel();
+        self.cancel = CancelFlag::default();
+        self.submitted = job.generation;
+        // the thread only ends when the worker is dropped
+        let _ = self.jobs.send((job, self.cancel.clone()));
+    }
+
+    /// the newest output that finished since the last call
+    pub fn poll(&mut self) -> Option<TraceOutput> {
+        let output = self.outputs.try_iter().last()?;
+        self.finished = output.generation;
+        Some(output)
+    }
+
+    /// whether the newest submitted job has not finished yet
+    pub fn is_tracing(&self) -> bool {
+        self.finished != self.submitted
+    }
+}
+
+impl Drop for TraceWorker {
+    /// Cancels the job in flight so it stops competing with the foreground tracer.
+    /// The thread is detached instead of joined to not block the ui until the trace notices,
+    /// it ends once it finds the job channel closed
+    fn drop(&mut self) {
+        self.cancel.cancel();
+    }
+}
@@ -22,7 +22,13 @@ var texture: texture_2d<f32>;
 @binding(1)
 var r_sampler: sampler;
 
+// x: 1 / number of accumulated passes
+@group(0)
+@binding(2)
+var<uniform> scale: vec4<f32>;
+
 @fragment
 fn fs_main(@location(0) tex_coord: vec2<f32>) -> @location(0) vec4<f32> {
-    return textureSample(texture, r_sampler, tex_coord);
+    let color = textureSample(texture, r_sampler, tex_coord);
+    return vec4<f32>(color.rgb * scale.x, color.a);
 }
@@ -26,6 +26,8 @@ pub struct Renderer {
     egui_rpass: egui_wgpu::Renderer,
     texture_renderer: TextureRenderer,
     surface_config: SurfaceConfiguration,
+    /// add the lines to the render texture instead of clearing it
+    accumulate: bool,
     pub make_screenshot: bool,
 }
 
@@ -61,6 +63,10 @@ impl Renderer {
 
         let texture_renderer =
             TextureRenderer::init(device, surface_config, app.color_state_descriptor.clone());
+        app.tracer.density.resize(
+            surface_config.width as usize,
+            surface_config.height as usize,
+        );
 
         let egui_rpass = egui_wgpu::Renderer::new(
             device,
@@ -75,6 +81,7 @@ impl Renderer {
             egui_rpass,
             texture_renderer,
             surface_config: surface_config.clone(),
+            accumulate: false,
             make_screenshot: false,
         }
     }
@@ -133,6 +140,10 @@ impl Renderer {
         self.surface_config = surface_config.clone();
         self.texture_renderer
             .generate_render_texture(device, &self.surface_config);
+        app.tracer.density.resize(
+            surface_config.width as usize,
+            surface_config.height as usize,
+        );
         self.recreate_projection_bind_groups(device, queue, app);
     }
 
@@ -172,7 +183,11 @@ impl Renderer {
                 color_attachments: &[Some(RenderPassColorAttachment {
                     view: &view,
                     ops: Operations {
-                        load: LoadOp::Clear(wgpu::Color::BLACK),
+                        load: if self.accumulate {
+                            LoadOp::Load
+                        } else {
+                            LoadOp::Clear(wgpu::Color::BLACK)
+                        },
                         store: StoreOp::Store,
                     },
                     resolve_target: None,
@@ -338,7 +353,9 @@ impl Renderer {
         context: &egui::Context,
         scale_factor: f32,
     ) {
-        self.clear_render_texture(queue);
+        if !self.accumulate {
+            self.clear_render_texture(queue);
+        }
         self.render_to_texture(encoder);
         if app.recreate_pipelines {
             let (pipeline, _bind_group_layout, bind_group, _sampler) =
@@ -347,6 +364,7 @@ impl Renderer {
                     &self.surface_config,
                     &self.texture_renderer.shader,
                     &self.texture_renderer.render_texture,
+                    &self.texture_renderer.scale_buffer,
                     app.color_state_descriptor.clone(),
                 );
             self.texture_renderer.pipeline = pipeline;
@@ -358,6 +376,7 @@ impl Renderer {
             &self.texture_renderer.bind_group_layout,
             &self.texture_renderer.render_texture,
             &self.texture_renderer.sampler,
+            &self.texture_renderer.scale_buffer,
         );
 
         {
@@ -428,6 +447,16 @@ impl Renderer {
         scale_factor: f32,
     ) {
         let render_result = app.draw();
+        self.accumulate = !render_result.clear;
+        self.texture_renderer
+            .set_accumulated_passes(queue, render_result.accumulated_passes);
+        if let Some(density) = &render_result.density {
+            let (width, height) = (self.surface_config.width, self.surface_config.height);
+            if density.len() == (width * height) as usize {
+                self.texture_renderer
+                    .write_render_texture(queue, density, width, height);
+            }
+        }
         self.sub_rpass_lines
             .update_vertex_buffer(device, &render_result.lines);
         self.sub_rpass_triangles
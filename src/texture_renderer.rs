@@ -1,4 +1,5 @@
 use crate::renderer::Vertex;
+use half::f16;
 use wgpu::util::DeviceExt;
 use wgpu::*;
 
@@ -13,6 +14,8 @@ pub struct TextureRenderer {
     pub bind_group_layout: BindGroupLayout,
     pub bind_group: BindGroup,
     pub sampler: Sampler,
+    /// uniform holding the factor the accumulated render texture is scaled by
+    pub scale_buffer: Buffer,
     pub index_buffer_size: u32,
 }
 
@@ -85,11 +88,19 @@ impl TextureRenderer {
             source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!("render_to_texture.wgsl"))),
         });
 
+        // vec4 to meet the uniform alignment of webgl, only x is used
+        let scale_buffer = device.create_buffer_init(&util::BufferInitDescriptor {
+            label: Some("render to texture scale buffer"),
+            contents: bytemuck::cast_slice(&[1_f32, 0., 0., 0.]),
+            usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
+        });
+
         let (pipeline, bind_group_layout, bind_group, sampler) = TextureRenderer::create_pipeline(
             device,
             surface_config,
             &shader,
             &render_texture,
+            &scale_buffer,
             color_state_descriptor,
         );
 
@@ -102,15 +113,56 @@ impl TextureRenderer {
             bind_group_layout,
             bind_group,
             sampler,
+            scale_buffer,
             index_buffer_size,
         }
     }
 
+    /// the render texture holds the sum of `accumulated_passes` passes, 0 is treated like 1
+    pub fn set_accumulated_passes(&self, queue: &Queue, accumulated_passes: u32) {
+        let scale = 1. / accumulated_passes.max(1) as f32;
+        queue.write_buffer(
+            &self.scale_buffer,
+            0,
+            bytemuck::cast_slice(&[scale, 0., 0., 0.]),
+        );
+    }
+
+    /// replaces the content of the render texture by `image`, linear rgba row by row from the top
+    pub fn write_render_texture(&self, queue: &Queue, image: &[[f32; 4]], width: u32, height: u32) {
+        let texels: Vec<u8> = image
+            .iter()
+            .flatten()
+            .flat_map(|channel| f16::from_f32(*channel).to_le_bytes())
+            .collect();
+        queue.write_texture(
+            TexelCopyTextureInfo {
+                texture: &self.render_texture,
+                mip_level: 0,
+                origin: Origin3d::ZERO,
+                aspect: TextureAspect::All,
+            },
+            &texels,
+            TexelCopyBufferLayout {
+                offset: 0,
+                // Rgba16Float
+                bytes_per_row: Some(width * 8),
+                rows_per_image: Some(height),
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+
     pub fn create_pipeline(
         device: &Device,
         surface_config: &SurfaceConfiguration,
         shader: &ShaderModule,
         render_texture: &Texture,
+        scale_buffer: &Buffer,
         mut color_state_descriptor: ColorTargetState,
     ) -> (RenderPipeline, BindGroupLayout, BindGroup, Sampler) {
         color_state_descriptor.format = surface_config.format;
@@ -129,6 +181,7 @@ impl TextureRenderer {
             &bind_group_layout,
             render_texture,
             &sampler,
+            scale_buffer,
         );
 
         let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -191,6 +244,16 @@ impl TextureRenderer {
                     ty: BindingType::Sampler(SamplerBindingType::Filtering),
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: BindingType::Buffer {
+                        ty: BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: BufferSize::new(16),
+                    },
+                    count: None,
+                },
             ],
         })
     }
@@ -200,6 +263,7 @@ impl TextureRenderer {
         bind_group_layout: &BindGroupLayout,
         render_texture: &Texture,
         sampler: &Sampler,
+        scale_buffer: &Buffer,
     ) -> BindGroup {
         device.create_bind_group(&wgpu::BindGroupDescriptor {
             label: Some("render to texture bind group"),
@@ -215,6 +279,10 @@ impl TextureRenderer {
                     binding: 1,
                     resource: wgpu::BindingResource::Sampler(sampler),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: scale_buffer.as_entire_binding(),
+                },
             ],
         })
     }